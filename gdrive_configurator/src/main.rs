@@ -1,7 +1,8 @@
 #![windows_subsystem = "windows"]
 
 use regex::Regex;
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
+use serde_json::Value;
 use std::fs;
 use std::path::Path;
 use thiserror::Error;
@@ -20,13 +21,6 @@ struct ServiceAccount {
     // We don't need all fields, just the most important ones for validation
 }
 
-// Define a struct for the output config.json
-#[derive(Serialize)]
-struct Config {
-    local_folder_path: String,
-    gdrive_folder_id: String,
-}
-
 // Custom error types for clear feedback
 #[derive(Debug, Error)]
 enum AppError {
@@ -46,6 +40,8 @@ enum AppError {
     CredentialsParseError,
     #[error("The credentials file is not for a 'service_account'. Type found: {0}")]
     CredentialsInvalidType(String),
+    #[error("The existing config.json is not a valid JSON object; fix or remove it first.")]
+    ExistingConfigInvalid,
     #[error("Failed to save config.json: {0}")]
     ConfigSaveError(String),
     #[error("Failed to copy credentials.json: {0}")]
@@ -135,12 +131,19 @@ fn handle_save(ui: &AppWindow) -> Result<(), AppError> {
 
     // --- 5. All validation passed. Create and save files. ---
 
-    // Create the config object.
+    // Update an existing config.json in place, so settings made outside this
+    // tool (encryption, destination, after_upload, ...) are kept.
     // serde_json will correctly escape backslashes for Windows paths.
-    let config = Config {
-        local_folder_path: local_path,
-        gdrive_folder_id,
+    let mut config = match fs::read_to_string("config.json") {
+        Ok(existing) => serde_json::from_str::<Value>(&existing)
+            .ok()
+            .filter(Value::is_object)
+            .ok_or(AppError::ExistingConfigInvalid)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Value::Object(Default::default()),
+        Err(e) => return Err(AppError::ConfigSaveError(e.to_string())),
     };
+    config["local_folder_path"] = Value::String(local_path);
+    config["gdrive_folder_id"] = Value::String(gdrive_folder_id);
 
     // Serialize to a pretty-printed JSON string
     let config_json = serde_json::to_string_pretty(&config)
@@ -0,0 +1,310 @@
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::{EmailAlertConfig, SmtpSecurity, WebhookEvent};
+use crate::webhooks::Notification;
+
+// Alerts waiting for the next digest beyond this are counted, not kept
+const MAX_PENDING: usize = 200;
+const DIGEST_CHECK_SECS: u64 = 60;
+
+// Email alerts are collected and sent as one digest at most every
+// digest_interval_mins, so a burst of failures produces a single message
+struct EmailAlerts {
+    settings: EmailAlertConfig,
+    state: Mutex<AlertState>,
+}
+
+struct AlertState {
+    pending: Vec<String>,
+    dropped: usize,
+    last_sent: Option<Instant>,
+    // Service start counts as a success, so a quiet service only alerts after a full period
+    last_success: Instant,
+    stale_alerted: bool,
+}
+
+impl EmailAlerts {
+    fn new(settings: &EmailAlertConfig) -> Self {
+        EmailAlerts {
+            settings: settings.clone(),
+            state: Mutex::new(AlertState {
+                pending: Vec::new(),
+                dropped: 0,
+                last_sent: None,
+                last_success: Instant::now(),
+                stale_alerted: false,
+            }),
+        }
+    }
+
+    // Failures and quota warnings are queued for the digest; successes reset the
+    // "no successful upload" timer
+    fn record(&self, notification: &Notification) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match notification.event {
+            WebhookEvent::UploadSucceeded => {
+                state.last_success = Instant::now();
+                state.stale_alerted = false;
+            }
+            WebhookEvent::UploadFailed | WebhookEvent::QuotaWarning | WebhookEvent::ServiceDegraded => {
+                if state.pending.len() < MAX_PENDING {
+                    state.pending.push(format!("{}  {}", notification.timestamp, notification.message));
+                } else {
+                    state.dropped += 1;
+                }
+            }
+            WebhookEvent::ServiceStarted | WebhookEvent::ServiceStopped | WebhookEvent::SyncBatch => {}
+        }
+    }
+
+    async fn send_digest(&self, force: bool) {
+        let settings = &self.settings;
+
+        let (lines, dropped) = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(hours) = settings.no_success_hours.filter(|h| *h > 0) {
+                if !state.stale_alerted && state.last_success.elapsed() >= Duration::from_secs(hours * 3600) {
+                    state.pending.push(format!(
+                        "{}  No successful upload in the last {} hour(s)",
+                        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                        hours
+                    ));
+                    state.stale_alerted = true;
+                }
+            }
+
+            let interval = Duration::from_secs(settings.digest_interval_mins * 60);
+            let throttled = state.last_sent.is_some_and(|sent| sent.elapsed() < interval);
+            if state.pending.is_empty() || (throttled && !force) {
+                return;
+            }
+            state.last_sent = Some(Instant::now());
+            (std::mem::take(&mut state.pending), std::mem::take(&mut state.dropped))
+        };
+
+        let host = crate::gdrive_sync::source_hostname();
+        let subject = format!("[gdrive-stealth-sync] {} alert(s) on {}", lines.len() + dropped, host);
+        let mut body = format!("gdrive-stealth-sync on {} reported:\n\n{}\n", host, lines.join("\n"));
+        if dropped > 0 {
+            body.push_str(&format!("\n...and {} more; see the logs for details.\n", dropped));
+        }
+
+        if let Err(e) = send_email(settings, &subject, body).await {
+            log::error!("Failed to send alert email: {}", e);
+            // Keep the alerts for the next digest
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let mut retry = lines;
+            retry.append(&mut state.pending);
+            retry.truncate(MAX_PENDING);
+            state.pending = retry;
+            state.dropped += dropped;
+        }
+    }
+}
+
+static EMAIL_ALERTS: once_cell::sync::OnceCell<EmailAlerts> = once_cell::sync::OnceCell::new();
+
+// Checks the settings and starts the digest task on the current runtime
+pub fn init_email_alerts(settings: &EmailAlertConfig) -> Result<(), String> {
+    build_transport(settings)?;
+    build_message(settings, "", String::new())?;
+
+    if EMAIL_ALERTS.set(EmailAlerts::new(settings)).is_ok() {
+        tokio::spawn(async {
+            loop {
+                tokio::time::sleep(Duration::from_secs(DIGEST_CHECK_SECS)).await;
+                send_digest(false).await;
+            }
+        });
+    }
+    Ok(())
+}
+
+pub fn record(notification: &Notification) {
+    if let Some(alerts) = EMAIL_ALERTS.get() {
+        alerts.record(notification);
+    }
+}
+
+// Sends whatever is queued straight away, for runs that exit afterwards
+pub async fn flush() {
+    send_digest(true).await;
+}
+
+// Sends a single message with the configured settings, to check them
+pub async fn send_test_email(settings: &EmailAlertConfig) -> Result<(), String> {
+    let body = format!(
+        "This is a test message from gdrive-stealth-sync on {}.\nEmail alerts are configured correctly.\n",
+        crate::gdrive_sync::source_hostname()
+    );
+    send_email(settings, "[gdrive-stealth-sync] Test alert", body).await
+}
+
+async fn send_digest(force: bool) {
+    if let Some(alerts) = EMAIL_ALERTS.get() {
+        alerts.send_digest(force).await;
+    }
+}
+
+async fn send_email(settings: &EmailAlertConfig, subject: &str, body: String) -> Result<(), String> {
+    let transport = build_transport(settings)?;
+    let message = build_message(settings, subject, body)?;
+    transport
+        .send(message)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Failed to send email via {}:{}: {}", settings.host, settings.port, e))
+}
+
+fn build_transport(settings: &EmailAlertConfig) -> Result<AsyncSmtpTransport<Tokio1Executor>, String> {
+    let builder = match settings.security {
+        SmtpSecurity::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host),
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host),
+        // Plain connection, e.g. to a local SMTP stand-in while testing
+        SmtpSecurity::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)),
+    }
+    .map_err(|e| format!("Invalid SMTP settings for {}: {}", settings.host, e))?;
+
+    let mut builder = builder.port(settings.port);
+    if let Some(username) = &settings.username {
+        let password = settings.password.clone().unwrap_or_default();
+        builder = builder.credentials(Credentials::new(username.clone(), password));
+    }
+    Ok(builder.build())
+}
+
+fn build_message(settings: &EmailAlertConfig, subject: &str, body: String) -> Result<Message, String> {
+    let from: Mailbox = settings
+        .from
+        .parse()
+        .map_err(|e| format!("Invalid alerts.email.from address '{}': {}", settings.from, e))?;
+    if settings.to.is_empty() {
+        return Err("alerts.email.to has no recipients".to_string());
+    }
+
+    let mut message = Message::builder().from(from).subject(subject);
+    for recipient in &settings.to {
+        let mailbox: Mailbox = recipient
+            .parse()
+            .map_err(|e| format!("Invalid alerts.email.to address '{}': {}", recipient, e))?;
+        message = message.to(mailbox);
+    }
+    message.body(body).map_err(|e| format!("Failed to build alert email: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    // Just enough SMTP to accept messages; returns the port and the received
+    // message data
+    async fn smtp_stand_in() -> (u16, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let messages = received.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let messages = messages.clone();
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let command = line.to_ascii_uppercase();
+                        let reply: &[u8] = if command.starts_with("EHLO") || command.starts_with("HELO") {
+                            b"250 localhost\r\n"
+                        } else if command.starts_with("DATA") {
+                            writer.write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n").await.unwrap();
+                            let mut data = String::new();
+                            while let Ok(Some(line)) = lines.next_line().await {
+                                if line == "." {
+                                    break;
+                                }
+                                data.push_str(&line);
+                                data.push('\n');
+                            }
+                            messages.lock().unwrap().push(data);
+                            b"250 Queued\r\n"
+                        } else if command.starts_with("QUIT") {
+                            writer.write_all(b"221 Bye\r\n").await.unwrap();
+                            break;
+                        } else {
+                            b"250 OK\r\n"
+                        };
+                        writer.write_all(reply).await.unwrap();
+                    }
+                });
+            }
+        });
+        (port, received)
+    }
+
+    fn alerts_for(port: u16) -> EmailAlerts {
+        let settings: EmailAlertConfig = serde_json::from_value(serde_json::json!({
+            "host": "127.0.0.1",
+            "port": port,
+            "security": "none",
+            "from": "sync@example.com",
+            "to": ["ops@example.com"],
+            "digest_interval_mins": 60
+        }))
+        .unwrap();
+        EmailAlerts::new(&settings)
+    }
+
+    fn failure(n: usize) -> Notification {
+        Notification::new(WebhookEvent::UploadFailed, &format!("Failed to upload file{}.txt", n))
+    }
+
+    #[tokio::test]
+    async fn one_digest_per_throttle_window() {
+        let (port, received) = smtp_stand_in().await;
+        let alerts = alerts_for(port);
+
+        for n in 0..3 {
+            alerts.record(&failure(n));
+        }
+        alerts.send_digest(false).await;
+        // Inside the window: queued, not sent
+        alerts.record(&failure(3));
+        alerts.send_digest(false).await;
+
+        {
+            let messages = received.lock().unwrap();
+            assert_eq!(messages.len(), 1);
+            assert!(messages[0].contains("3 alert(s)"));
+            assert!(messages[0].contains("file2.txt"));
+            assert!(!messages[0].contains("file3.txt"));
+        }
+
+        // A flush at exit sends what is left regardless of the window
+        alerts.send_digest(true).await;
+        let messages = received.lock().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[1].contains("file3.txt"));
+    }
+
+    #[tokio::test]
+    async fn overflow_beyond_max_pending_is_reported() {
+        let (port, received) = smtp_stand_in().await;
+        let alerts = alerts_for(port);
+
+        for n in 0..MAX_PENDING + 5 {
+            alerts.record(&failure(n));
+        }
+        alerts.send_digest(false).await;
+
+        let messages = received.lock().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains(&format!("{} alert(s)", MAX_PENDING + 5)));
+        assert!(messages[0].contains("...and 5 more"));
+    }
+}
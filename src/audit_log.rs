@@ -0,0 +1,254 @@
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::config::{AuditConfig, AuditFormat};
+
+// Append-only record of every file that left the machine. Kept apart from the
+// diagnostic logs: one file per month, only deleted when `retention_days` is set.
+pub struct AuditLog {
+    dir: PathBuf,
+    format: AuditFormat,
+    retention: Option<Duration>,
+    current: Mutex<Option<(String, BufWriter<File>)>>,
+}
+
+#[derive(Serialize)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    pub local_path: String,
+    pub size: u64,
+    pub sha256: String,
+    pub drive_file_id: String,
+    pub destination_folder: String,
+    pub outcome: String,
+    pub error: String,
+}
+
+impl AuditEntry {
+    pub fn new(local_path: &str, destination_folder: &str, outcome: &str) -> Self {
+        AuditEntry {
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            local_path: local_path.to_string(),
+            size: 0,
+            sha256: String::new(),
+            drive_file_id: String::new(),
+            destination_folder: destination_folder.to_string(),
+            outcome: outcome.to_string(),
+            error: String::new(),
+        }
+    }
+
+    fn to_csv_row(&self) -> String {
+        [
+            csv_field(&self.timestamp),
+            csv_field(&self.local_path),
+            self.size.to_string(),
+            csv_field(&self.sha256),
+            csv_field(&self.drive_file_id),
+            csv_field(&self.destination_folder),
+            csv_field(&self.outcome),
+            csv_field(&self.error),
+        ]
+        .join(",")
+    }
+}
+
+const CSV_HEADER: &str = "timestamp,local_path,size,sha256,drive_file_id,destination_folder,outcome,error";
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl AuditLog {
+    pub fn new(dir: PathBuf, settings: &AuditConfig) -> Result<Self, std::io::Error> {
+        std::fs::create_dir_all(&dir)?;
+        let log = AuditLog {
+            dir,
+            format: settings.format,
+            retention: settings.retention_days.map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+            current: Mutex::new(None),
+        };
+        log.apply_retention();
+        Ok(log)
+    }
+
+    pub fn record(&self, entry: &AuditEntry) -> Result<(), std::io::Error> {
+        self.record_at(entry, chrono::Utc::now())
+    }
+
+    fn record_at(&self, entry: &AuditEntry, now: chrono::DateTime<chrono::Utc>) -> Result<(), std::io::Error> {
+        let line = match self.format {
+            AuditFormat::Jsonl => serde_json::to_string(entry)?,
+            AuditFormat::Csv => entry.to_csv_row(),
+        };
+
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        let month = now.format("%Y-%m").to_string();
+        let rolled_over = current.as_ref().map(|(m, _)| m != &month).unwrap_or(true);
+
+        if rolled_over {
+            let path = self.dir.join(format!("uploads_{}.{}", month, self.format.extension()));
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let is_new = file.metadata()?.len() == 0;
+            let mut writer = BufWriter::new(file);
+            if is_new && self.format == AuditFormat::Csv {
+                writeln!(writer, "{}", CSV_HEADER)?;
+            }
+            *current = Some((month, writer));
+            self.apply_retention();
+        }
+
+        if let Some((_, writer)) = current.as_mut() {
+            writeln!(writer, "{}", line)?;
+            // Flush every entry so a crash never loses a record
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    // Deletes monthly files whose last entry is older than the retention period.
+    // Without an explicit retention setting nothing is ever removed.
+    fn apply_retention(&self) {
+        let retention = match self.retention {
+            Some(r) => r,
+            None => return,
+        };
+
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        let now = SystemTime::now();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_audit_file = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .map(|n| n.starts_with("uploads_"))
+                .unwrap_or(false);
+            if !is_audit_file {
+                continue;
+            }
+            let expired = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok())
+                .map(|age| age > retention)
+                .unwrap_or(false);
+            if expired {
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+    }
+}
+
+static AUDIT_LOG: once_cell::sync::OnceCell<AuditLog> = once_cell::sync::OnceCell::new();
+
+pub fn init_audit_log(dir: PathBuf, settings: &AuditConfig) -> Result<(), std::io::Error> {
+    let log = AuditLog::new(dir, settings)?;
+    let _ = AUDIT_LOG.set(log);
+    Ok(())
+}
+
+pub fn record(entry: &AuditEntry) {
+    if let Some(log) = AUDIT_LOG.get() {
+        if let Err(e) = log.record(entry) {
+            log::error!("Failed to write audit record for '{}': {}", entry.local_path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+    use chrono::TimeZone;
+
+    fn settings(format: AuditFormat, retention_days: Option<u64>) -> AuditConfig {
+        AuditConfig {
+            format,
+            retention_days,
+            ..AuditConfig::default()
+        }
+    }
+
+    fn file_names(dir: &std::path::Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn csv_fields_with_separators_are_quoted() {
+        let mut entry = AuditEntry::new("C:\\Scans\\a, b.pdf", "folder", "uploaded");
+        entry.timestamp = "2024-05-01T10:00:00.000Z".to_string();
+        entry.error = "said \"no\"\r\nthen gave up".to_string();
+        entry.size = 42;
+
+        assert_eq!(
+            entry.to_csv_row(),
+            "2024-05-01T10:00:00.000Z,\"C:\\Scans\\a, b.pdf\",42,,,folder,uploaded,\"said \"\"no\"\"\r\nthen gave up\""
+        );
+        assert_eq!(csv_field("plain name.txt"), "plain name.txt");
+        assert_eq!(csv_field("line\nbreak"), "\"line\nbreak\"");
+    }
+
+    #[test]
+    fn entries_roll_over_into_a_file_per_month() {
+        let dir = temp_dir("audit-rollover");
+        let log = AuditLog::new(dir.clone(), &settings(AuditFormat::Csv, None)).unwrap();
+        let entry = AuditEntry::new("a.pdf", "folder", "uploaded");
+
+        let may = chrono::Utc.with_ymd_and_hms(2024, 5, 31, 23, 59, 59).unwrap();
+        log.record_at(&entry, may).unwrap();
+        // A new day, or a large file, stays in the month's file
+        log.record_at(&entry, may - chrono::Duration::days(1)).unwrap();
+        let june = chrono::Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        log.record_at(&entry, june).unwrap();
+
+        assert_eq!(file_names(&dir), ["uploads_2024-05.csv", "uploads_2024-06.csv"]);
+        let may_rows = std::fs::read_to_string(dir.join("uploads_2024-05.csv")).unwrap();
+        assert_eq!(may_rows.lines().count(), 3);
+        assert_eq!(may_rows.lines().next(), Some(CSV_HEADER));
+
+        // Reopening appends without a second header
+        drop(log);
+        let log = AuditLog::new(dir.clone(), &settings(AuditFormat::Csv, None)).unwrap();
+        log.record_at(&entry, june).unwrap();
+        let june_rows = std::fs::read_to_string(dir.join("uploads_2024-06.csv")).unwrap();
+        assert_eq!(june_rows.lines().filter(|line| *line == CSV_HEADER).count(), 1);
+        assert_eq!(june_rows.lines().count(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn retention_removes_only_expired_audit_files() {
+        let dir = temp_dir("audit-retention");
+        let old = SystemTime::now() - Duration::from_secs(40 * 24 * 60 * 60);
+        for name in ["uploads_2020-01.jsonl", "notes.txt"] {
+            let file = File::create(dir.join(name)).unwrap();
+            file.set_modified(old).unwrap();
+        }
+        File::create(dir.join("uploads_2020-02.jsonl")).unwrap();
+
+        // No retention setting keeps everything
+        AuditLog::new(dir.clone(), &settings(AuditFormat::Jsonl, None)).unwrap();
+        assert_eq!(file_names(&dir).len(), 3);
+
+        AuditLog::new(dir.clone(), &settings(AuditFormat::Jsonl, Some(30))).unwrap();
+        assert_eq!(file_names(&dir), ["notes.txt", "uploads_2020-02.jsonl"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::alerts;
+use crate::config;
+use crate::doctor;
+use crate::encryption::{self, KeyMaterial};
+use crate::gdrive_sync;
+use crate::name_manifest;
+
+// Command-line entry points used for maintenance outside the Windows service.
+// Each returns the process exit code.
+
+const DECRYPT_USAGE: &str = "Usage: gdrive-stealth-sync decrypt <file-or-folder> [output] [--key-file <path> | --passphrase-file <path>] [--manifest <path>]";
+
+// Restores files downloaded from Drive. Without --key-file or --passphrase-file the
+// key configured in config.json is used. Folders are decrypted recursively. When a
+// name manifest is given (or found in the folder), files uploaded under encrypted
+// names are restored to their original names and directory structure.
+pub fn run_decrypt(args: &[String]) -> i32 {
+    let mut positional = Vec::new();
+    let mut key_file = None;
+    let mut passphrase_file = None;
+    let mut manifest_file = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--key-file" => key_file = iter.next().cloned(),
+            "--passphrase-file" => passphrase_file = iter.next().cloned(),
+            "--manifest" => manifest_file = iter.next().map(PathBuf::from),
+            "-h" | "--help" => {
+                println!("{}", DECRYPT_USAGE);
+                return 0;
+            }
+            _ => positional.push(PathBuf::from(arg)),
+        }
+    }
+
+    let input = match positional.first() {
+        Some(path) => path.clone(),
+        None => {
+            eprintln!("{}", DECRYPT_USAGE);
+            return 2;
+        }
+    };
+
+    let key = match (key_file, passphrase_file) {
+        (Some(path), None) => KeyMaterial::from_key_file(Path::new(&path)),
+        (None, Some(path)) => KeyMaterial::from_passphrase_file(Path::new(&path)),
+        (Some(_), Some(_)) => Err("Use only one of --key-file and --passphrase-file".to_string()),
+        (None, None) => config::load_config().and_then(|c| KeyMaterial::from_config(&c.encryption)),
+    };
+    let key = match key {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    let manifest_file = manifest_file.or_else(|| {
+        let candidate = input.join(name_manifest::MANIFEST_NAME);
+        candidate.is_file().then_some(candidate)
+    });
+    let names = match manifest_file {
+        Some(path) => match name_manifest::read_entries(&path, &key) {
+            Ok(entries) => {
+                println!("Using name manifest {:?} ({} entries)", path, entries.len());
+                entries
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        },
+        None => BTreeMap::new(),
+    };
+
+    let result = if input.is_dir() {
+        let output = positional.get(1).cloned().unwrap_or_else(|| input.clone());
+        decrypt_folder(&input, &output, &key, &names)
+    } else {
+        let output = positional.get(1).cloned().unwrap_or_else(|| restored_name(&input, &names));
+        encryption::decrypt_file(&input, &output, &key).map(|_| {
+            println!("Decrypted {:?} -> {:?}", input, output);
+            1
+        })
+    };
+
+    match result {
+        Ok(count) => {
+            println!("{} file(s) decrypted", count);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+// Uses the manifest's original relative path when the file was uploaded under an
+// encrypted name, otherwise strips the marker extension: "report.pdf.enc" -> "report.pdf"
+fn restored_name(path: &Path, names: &BTreeMap<String, String>) -> PathBuf {
+    let original = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(|n| names.get(n));
+    if let Some(relative_path) = original {
+        let parent = path.parent().unwrap_or(Path::new(""));
+        return relative_path.split('/').fold(parent.to_path_buf(), |acc, part| acc.join(part));
+    }
+    match path.extension() {
+        Some(ext) if ext == encryption::ENCRYPTED_EXTENSION => path.with_extension(""),
+        _ => path.with_extension("decrypted"),
+    }
+}
+
+fn decrypt_folder(input: &Path, output: &Path, key: &KeyMaterial, names: &BTreeMap<String, String>) -> Result<usize, String> {
+    let entries = std::fs::read_dir(input).map_err(|e| format!("Failed to read folder {:?}: {}", input, e))?;
+    std::fs::create_dir_all(output).map_err(|e| format!("Failed to create folder {:?}: {}", output, e))?;
+
+    let mut count = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            count += decrypt_folder(&path, &output.join(entry.file_name()), key, names)?;
+        } else if entry.file_name() == name_manifest::MANIFEST_NAME {
+            continue;
+        } else if path.extension().is_some_and(|ext| ext == encryption::ENCRYPTED_EXTENSION) {
+            let target = restored_name(&output.join(entry.file_name()), names);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create folder {:?}: {}", parent, e))?;
+            }
+            encryption::decrypt_file(&path, &target, key)?;
+            println!("Decrypted {:?} -> {:?}", path, target);
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+// Runs the service pipeline in the console against config.json without writing
+// anything: existing files are reconciled, then new files are planned as they
+// arrive. Ctrl+C stops the run and prints the summary.
+pub fn run_dry_run() -> i32 {
+    if let Err(e) = crate::initialize_logging(true) {
+        eprintln!("Failed to initialize logging: {}", e);
+        return 1;
+    }
+
+    let rt = match tokio::runtime::Runtime::new() {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("Failed to create tokio runtime: {}", e);
+            return 1;
+        }
+    };
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let result = rt.block_on(async {
+        let ctrl_c = stop.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                ctrl_c.store(true, Ordering::SeqCst);
+            }
+        });
+        println!("Dry run started; press Ctrl+C to stop and print the summary");
+        gdrive_sync::run_dry_run(&stop).await
+    });
+
+    match result {
+        Ok(plan) => {
+            log::info!("{}", plan.summary());
+            if plan.errors > 0 { 1 } else { 0 }
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            1
+        }
+    }
+}
+
+// Pushes whatever is new or changed, then exits: one reconciliation pass with the
+// usual retries. Exits with 1 when any file could not be uploaded.
+pub fn run_sync_once() -> i32 {
+    if let Err(e) = crate::initialize_logging(true) {
+        eprintln!("Failed to initialize logging: {}", e);
+        return 1;
+    }
+
+    let rt = match tokio::runtime::Runtime::new() {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("Failed to create tokio runtime: {}", e);
+            return 1;
+        }
+    };
+
+    match rt.block_on(gdrive_sync::run_sync_once()) {
+        Ok(summary) => {
+            log::info!("{}", summary.summary());
+            if summary.failed > 0 { 1 } else { 0 }
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            1
+        }
+    }
+}
+
+// Sends one message with the alerts.email settings from config.json
+pub fn run_test_email() -> i32 {
+    let settings = match config::load_config() {
+        Ok(config) => match config.alerts.email {
+            Some(settings) => settings,
+            None => {
+                eprintln!("alerts.email is not configured in config.json");
+                return 1;
+            }
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    let rt = match tokio::runtime::Runtime::new() {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("Failed to create tokio runtime: {}", e);
+            return 1;
+        }
+    };
+
+    match rt.block_on(alerts::send_test_email(&settings)) {
+        Ok(()) => {
+            println!("Test email sent to {}", settings.to.join(", "));
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+// Checks the installation step by step (configuration, folders, credentials,
+// network, destination, quota) and prints what failed with a hint for each.
+// Exits with 1 when any check failed.
+pub fn run_doctor() -> i32 {
+    let rt = match tokio::runtime::Runtime::new() {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("Failed to create tokio runtime: {}", e);
+            return 1;
+        }
+    };
+
+    let mut report = doctor::Report::default();
+    rt.block_on(doctor::run_checks(&mut report));
+    report.print();
+    if report.failed() { 1 } else { 0 }
+}
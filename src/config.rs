@@ -0,0 +1,539 @@
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Deserialize)]
+pub struct Config {
+    pub local_folder_path: String,
+    // Only needed when the destination is Google Drive
+    #[serde(default)]
+    pub gdrive_folder_id: String,
+    // Where uploads go; Google Drive unless configured otherwise
+    #[serde(default)]
+    pub destination: DestinationConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub after_upload: AfterUploadConfig,
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+    #[serde(default)]
+    pub upload: UploadConfig,
+    #[serde(default)]
+    pub watch: WatchConfig,
+    // How symbolic links in the watched folder are treated
+    #[serde(default)]
+    pub symlinks: SymlinkPolicy,
+    #[serde(default)]
+    pub quota: QuotaConfig,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+    // Proxy and extra CA roots for all outgoing connections
+    #[serde(default)]
+    pub network: NetworkConfig,
+    // Endpoint overrides, e.g. for a local mock of the Drive API
+    #[serde(default)]
+    pub drive_api: DriveApiConfig,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct NetworkConfig {
+    pub proxy: Option<ProxyConfig>,
+    // PEM files with CA certificates to trust in addition to the system's
+    pub ca_bundles: Vec<String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ProxyConfig {
+    // http://host:port; HTTPS traffic is tunnelled with CONNECT
+    pub url: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    // Hosts (and their subdomains) reached directly
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct DriveApiConfig {
+    // Replaces https://www.googleapis.com/drive/v3/
+    pub base_url: Option<String>,
+    // Replaces https://www.googleapis.com/ for uploads, which go to <upload_url>upload/drive/v3/files
+    pub upload_url: Option<String>,
+    // Replaces the token_uri from credentials.json
+    pub token_uri: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum DestinationConfig {
+    // The folder given by gdrive_folder_id, using credentials.json
+    #[default]
+    Drive,
+    // A local or mounted directory, e.g. a USB disk or NAS share
+    Local { path: String },
+    // An S3-compatible bucket (AWS, MinIO, Wasabi, ...)
+    S3(S3Config),
+    // A WebDAV collection, e.g. a Nextcloud or ownCloud folder
+    Webdav(WebdavConfig),
+    // A directory on an SFTP server
+    Sftp(SftpConfig),
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SftpConfig {
+    pub host: String,
+    #[serde(default = "default_sftp_port")]
+    pub port: u16,
+    pub username: String,
+    // OpenSSH private key file; relative paths are resolved against the executable's directory
+    pub private_key: String,
+    #[serde(default)]
+    pub passphrase: Option<String>,
+    // OpenSSH known_hosts file the server's host key must be listed in
+    #[serde(default = "default_known_hosts")]
+    pub known_hosts: String,
+    // Directory uploads go into; created if missing
+    pub remote_dir: String,
+}
+
+fn default_sftp_port() -> u16 {
+    22
+}
+
+fn default_known_hosts() -> String {
+    "known_hosts".to_string()
+}
+
+#[derive(Deserialize, Clone)]
+pub struct WebdavConfig {
+    // Collection uploads go into, e.g. https://cloud.example.com/remote.php/dav/files/alice/Scans
+    pub url: String,
+    pub username: String,
+    // Account password or, preferably, a Nextcloud/ownCloud app password
+    pub password: String,
+    // Nextcloud chunked upload endpoint; derived from a .../remote.php/dav/files/<user>/ URL when unset
+    #[serde(default)]
+    pub chunked_upload_url: Option<String>,
+    // Files larger than this are uploaded in chunks of this size, where chunking is available
+    #[serde(default = "default_webdav_chunk_size")]
+    pub chunk_size_mb: u64,
+}
+
+fn default_webdav_chunk_size() -> u64 {
+    10
+}
+
+#[derive(Deserialize, Clone)]
+pub struct S3Config {
+    pub bucket: String,
+    // Prepended to every object key, e.g. "backups/scanner"
+    #[serde(default)]
+    pub prefix: String,
+    // Unset means AWS: https://s3.<region>.amazonaws.com
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+    // Fall back to AWS_ACCESS_KEY_ID / AWS_SECRET_ACCESS_KEY when unset
+    #[serde(default)]
+    pub access_key_id: Option<String>,
+    #[serde(default)]
+    pub secret_access_key: Option<String>,
+    // Bucket in the path instead of the host name; defaults to on for custom endpoints
+    #[serde(default)]
+    pub path_style: Option<bool>,
+    // Files larger than this are sent as a multipart upload in parts of this size
+    #[serde(default = "default_s3_part_size")]
+    pub part_size_mb: u64,
+}
+
+fn default_s3_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_s3_part_size() -> u64 {
+    16
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct AlertsConfig {
+    pub email: Option<EmailAlertConfig>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct EmailAlertConfig {
+    pub host: String,
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    // Alerts are batched into one email at most this often
+    #[serde(default = "default_digest_interval")]
+    pub digest_interval_mins: u64,
+    // Alert when nothing has been uploaded for this long; unset disables it
+    #[serde(default)]
+    pub no_success_hours: Option<u64>,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_digest_interval() -> u64 {
+    60
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    #[default]
+    Starttls,
+    // TLS from the first byte, usually port 465
+    Tls,
+    // Unencrypted, for local relays and test servers only
+    None,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct QuotaConfig {
+    // Raise a quota warning once Drive storage use reaches this share of the
+    // limit; 0 disables the check
+    pub warning_percent: u8,
+    // Storage use is checked after an upload at most this often
+    pub check_interval_mins: u64,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self {
+            warning_percent: 90,
+            check_interval_mins: 60,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    // Signs each payload with HMAC-SHA256 in the X-Signature-256 header
+    #[serde(default)]
+    pub secret: Option<String>,
+    // Events to send; empty sends all of them except upload_succeeded
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+    #[serde(default = "default_webhook_attempts")]
+    pub max_attempts: u32,
+    // Delay before the first retry; doubled for each further attempt
+    #[serde(default = "default_webhook_retry_delay")]
+    pub retry_delay_secs: u64,
+}
+
+fn default_webhook_attempts() -> u32 {
+    5
+}
+
+fn default_webhook_retry_delay() -> u64 {
+    10
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    UploadSucceeded,
+    // The upload was given up after the last retry
+    UploadFailed,
+    QuotaWarning,
+    ServiceStarted,
+    ServiceStopped,
+    // A startup check failed; the service keeps retrying
+    ServiceDegraded,
+    // Counts from a sync pass that uploaded or failed at least one file
+    SyncBatch,
+}
+
+impl WebhookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::UploadSucceeded => "upload_succeeded",
+            WebhookEvent::UploadFailed => "upload_failed",
+            WebhookEvent::QuotaWarning => "quota_warning",
+            WebhookEvent::ServiceStarted => "service_started",
+            WebhookEvent::ServiceStopped => "service_stopped",
+            WebhookEvent::ServiceDegraded => "service_degraded",
+            WebhookEvent::SyncBatch => "sync_batch",
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    Skip,
+    Follow,
+    // Follow only links whose target is inside the watched folder
+    #[default]
+    FollowWithinRoot,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct WatchConfig {
+    pub backend: WatchBackend,
+    // How often the "poll" backend (or "auto" after falling back) lists the folder
+    pub poll_interval_secs: u64,
+    // Compare the folder with the sync state this often to pick up files the
+    // watcher missed; 0 disables periodic rescans
+    pub rescan_interval_secs: u64,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            backend: WatchBackend::Auto,
+            poll_interval_secs: 30,
+            rescan_interval_secs: 900,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchBackend {
+    // Native change notifications, falling back to polling if they cannot be set up
+    Auto,
+    Native,
+    // Periodic directory listing, for network shares that deliver no notifications
+    Poll,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct UploadConfig {
+    // Attempts per file, counting both failed requests and failed verification
+    pub max_attempts: u32,
+    // Delay before the first retry; doubled for each further attempt
+    pub retry_delay_secs: u64,
+    // What to do when the destination folder already has a file with the same name
+    pub on_name_conflict: NameConflictPolicy,
+    // Suffix style used by the "rename" policy
+    pub rename_suffix: RenameSuffix,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            retry_delay_secs: 5,
+            on_name_conflict: NameConflictPolicy::Duplicate,
+            rename_suffix: RenameSuffix::Number,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NameConflictPolicy {
+    // Upload alongside the existing file (Drive allows duplicate names)
+    Duplicate,
+    Skip,
+    // Upload as a new revision of the existing file
+    Overwrite,
+    Rename,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RenameSuffix {
+    // "report (1).pdf"
+    Number,
+    // "report_20250101_120000.pdf"
+    Timestamp,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    // "text" (default) or "json" for JSON Lines output
+    pub format: LogFormat,
+    // Global level plus optional overrides keyed by module path prefix,
+    // e.g. { "gdrive_stealth_sync::gdrive_sync": "debug", "hyper": "warn" }
+    pub level: LevelFilter,
+    pub modules: HashMap<String, LevelFilter>,
+    // Relative paths are resolved against the executable's directory
+    pub directory: String,
+    pub max_file_size_mb: u64,
+    // Plain-text files kept; with compress_rotated, gzipped files only count
+    // toward max_total_size_mb
+    pub max_files: usize,
+    // Disk budget for all log files together; defaults to what max_files
+    // uncompressed files of max_file_size_mb would take
+    pub max_total_size_mb: Option<u64>,
+    // Files older than this are removed during cleanup; unset keeps them until the
+    // count or size limits
+    pub max_age_days: Option<u64>,
+    // Start a new file at midnight UTC in addition to size-based rotation
+    pub daily_rotation: bool,
+    // Gzip each log file once it has been rotated out
+    pub compress_rotated: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Text,
+            level: LevelFilter::Info,
+            modules: HashMap::new(),
+            directory: "logs".to_string(),
+            max_file_size_mb: 2,
+            max_files: 5,
+            max_total_size_mb: None,
+            max_age_days: None,
+            daily_rotation: false,
+            compress_rotated: true,
+        }
+    }
+}
+
+impl LoggingConfig {
+    pub fn log_dir(&self) -> Result<PathBuf, String> {
+        resolve_path(&self.directory)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub enabled: bool,
+    // Relative paths are resolved against the executable's directory
+    pub directory: String,
+    pub format: AuditFormat,
+    // Monthly audit files older than this are deleted; unset keeps them forever
+    pub retention_days: Option<u64>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            directory: "audit".to_string(),
+            format: AuditFormat::Jsonl,
+            retention_days: None,
+        }
+    }
+}
+
+impl AuditConfig {
+    pub fn audit_dir(&self) -> Result<PathBuf, String> {
+        resolve_path(&self.directory)
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditFormat {
+    Jsonl,
+    Csv,
+}
+
+impl AuditFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AuditFormat::Jsonl => "jsonl",
+            AuditFormat::Csv => "csv",
+        }
+    }
+}
+
+// What happens to the local copy once Drive has a verified copy of it
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct AfterUploadConfig {
+    pub action: AfterUploadAction,
+    // Required for "move"; relative paths are resolved against the executable's directory
+    pub archive_dir: Option<String>,
+    // Move into archive_dir/YYYY/MM/DD instead of archive_dir directly
+    pub date_subfolders: bool,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AfterUploadAction {
+    #[default]
+    Keep,
+    Delete,
+    Move,
+}
+
+// Client-side encryption of file contents before upload. Exactly one of
+// key_file (32 raw bytes or 64 hex characters) or passphrase_file must be set.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    pub enabled: bool,
+    pub key_file: Option<String>,
+    pub passphrase_file: Option<String>,
+    // Upload under deterministic keyed-hash names; the real names go into an
+    // encrypted manifest stored in the Drive folder
+    pub encrypt_names: bool,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+// All runtime files (config, credentials, logs) live next to the executable
+pub fn base_dir() -> Result<PathBuf, String> {
+    let exe_path = std::env::current_exe()
+        .map_err(|e| format!("Failed to get current executable path: {}", e))?;
+    exe_path
+        .parent()
+        .map(|dir| dir.to_path_buf())
+        .ok_or_else(|| "Failed to get parent directory of executable".to_string())
+}
+
+// Relative paths in config.json are resolved against the executable's directory
+pub fn resolve_path(path: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(path);
+    if path.is_absolute() {
+        Ok(path)
+    } else {
+        Ok(base_dir()?.join(path))
+    }
+}
+
+pub fn load_config() -> Result<Config, String> {
+    let config_path = base_dir()?.join("config.json");
+    let content = fs::read_to_string(&config_path)
+        .map_err(|e| format!("Failed to read config.json: {}", e))?;
+    let config: Config = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse config.json: {}", e))?;
+
+    // Names would otherwise go up in clear against the user's intent
+    if config.encryption.encrypt_names && !config.encryption.enabled {
+        return Err("encryption.encrypt_names requires encryption.enabled to be true".to_string());
+    }
+    Ok(config)
+}
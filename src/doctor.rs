@@ -0,0 +1,445 @@
+use http_body_util::Empty;
+use hyper::body::Bytes;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::{self, Config, DestinationConfig};
+use crate::network;
+use crate::storage;
+
+// Per network request, and for connecting to the destination as a whole
+const REQUEST_TIMEOUT_SECS: u64 = 15;
+const DESTINATION_TIMEOUT_SECS: u64 = 90;
+// Google rejects signed token requests from clocks this far off
+const CLOCK_SKEW_WARN_SECS: i64 = 30;
+const CLOCK_SKEW_FAIL_SECS: i64 = 120;
+
+const DEFAULT_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+const DEFAULT_DRIVE_URL: &str = "https://www.googleapis.com/drive/v3/";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+    Skip,
+}
+
+struct Finding {
+    status: Status,
+    check: &'static str,
+    detail: String,
+    hint: Option<String>,
+}
+
+// Results of every check, printed as a pass/fail list with fix hints
+#[derive(Default)]
+pub struct Report {
+    findings: Vec<Finding>,
+}
+
+impl Report {
+    fn add(&mut self, status: Status, check: &'static str, detail: String, hint: Option<String>) {
+        self.findings.push(Finding { status, check, detail, hint });
+    }
+
+    fn pass(&mut self, check: &'static str, detail: String) {
+        self.add(Status::Pass, check, detail, None);
+    }
+
+    fn warn(&mut self, check: &'static str, detail: String, hint: &str) {
+        self.add(Status::Warn, check, detail, Some(hint.to_string()));
+    }
+
+    fn fail(&mut self, check: &'static str, detail: String, hint: &str) {
+        self.add(Status::Fail, check, detail, Some(hint.to_string()));
+    }
+
+    fn skip(&mut self, check: &'static str, detail: &str) {
+        self.add(Status::Skip, check, detail.to_string(), None);
+    }
+
+    pub fn failed(&self) -> bool {
+        self.findings.iter().any(|f| f.status == Status::Fail)
+    }
+
+    pub fn print(&self) {
+        for finding in &self.findings {
+            let label = match finding.status {
+                Status::Pass => "PASS",
+                Status::Warn => "WARN",
+                Status::Fail => "FAIL",
+                Status::Skip => "SKIP",
+            };
+            println!("[{}] {}: {}", label, finding.check, finding.detail);
+            if let Some(hint) = &finding.hint {
+                println!("       Fix: {}", hint);
+            }
+        }
+
+        let count = |status| self.findings.iter().filter(|f| f.status == status).count();
+        println!();
+        println!(
+            "{} passed, {} warning(s), {} failed, {} skipped",
+            count(Status::Pass), count(Status::Warn), count(Status::Fail), count(Status::Skip)
+        );
+    }
+}
+
+pub async fn run_checks(report: &mut Report) {
+    let base_dir = match config::base_dir() {
+        Ok(dir) => dir,
+        Err(e) => return report.fail("Configuration", e, "Run the executable from its installation folder"),
+    };
+    let config = match config::load_config() {
+        Ok(config) => {
+            report.pass("Configuration", format!("{:?} parsed", base_dir.join("config.json")));
+            config
+        }
+        Err(e) => {
+            report.fail(
+                "Configuration",
+                e,
+                "Copy config.json.template to config.json next to the executable and fix the reported JSON error",
+            );
+            return;
+        }
+    };
+
+    check_local_folder(report, &config);
+    check_log_dir(report, &config);
+    let token_uri = check_credentials(report, &base_dir, &config);
+    check_network(report, &config, token_uri).await;
+    check_destination(report, &base_dir, &config).await;
+    check_service_status(report, &base_dir);
+}
+
+fn check_local_folder(report: &mut Report, config: &Config) {
+    const CHECK: &str = "Watched folder";
+    let path = Path::new(&config.local_folder_path);
+    if !path.exists() {
+        return report.fail(
+            CHECK,
+            format!("'{}' does not exist", config.local_folder_path),
+            "Create the folder or fix local_folder_path (use double backslashes in JSON)",
+        );
+    }
+    if !path.is_dir() {
+        return report.fail(CHECK, format!("'{}' is not a folder", config.local_folder_path), "Point local_folder_path at a folder");
+    }
+    match fs::read_dir(path) {
+        Ok(entries) => report.pass(CHECK, format!("'{}' is readable ({} entries)", config.local_folder_path, entries.count())),
+        Err(e) => report.fail(
+            CHECK,
+            format!("'{}' cannot be read: {}", config.local_folder_path, e),
+            "Give the account the service runs as (LocalSystem by default) read access to the folder",
+        ),
+    }
+}
+
+fn check_log_dir(report: &mut Report, config: &Config) {
+    const CHECK: &str = "Log directory";
+    let dir = match config.logging.log_dir() {
+        Ok(dir) => dir,
+        Err(e) => return report.fail(CHECK, e, "Fix logging.directory in config.json"),
+    };
+    let probe = dir.join(".doctor-probe");
+    let result = fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(&probe, b"probe"))
+        .and_then(|_| fs::remove_file(&probe));
+    match result {
+        Ok(()) => report.pass(CHECK, format!("{:?} is writable", dir)),
+        Err(e) => report.fail(
+            CHECK,
+            format!("{:?} is not writable: {}", dir, e),
+            "Give the service account write access or change logging.directory",
+        ),
+    }
+}
+
+// Checks the credentials the destination needs. Returns the token URI for the
+// clock check when the destination is Google Drive.
+fn check_credentials(report: &mut Report, base_dir: &Path, config: &Config) -> Option<String> {
+    const CHECK: &str = "Credentials";
+    match &config.destination {
+        DestinationConfig::Drive => {}
+        DestinationConfig::Local { .. } => {
+            report.skip(CHECK, "not needed for a local destination");
+            return None;
+        }
+        DestinationConfig::S3(settings) => {
+            let has_key = settings.access_key_id.is_some() || std::env::var("AWS_ACCESS_KEY_ID").is_ok();
+            let has_secret = settings.secret_access_key.is_some() || std::env::var("AWS_SECRET_ACCESS_KEY").is_ok();
+            if has_key && has_secret {
+                report.pass(CHECK, "S3 access key and secret key are set".to_string());
+            } else {
+                report.fail(
+                    CHECK,
+                    "S3 access key or secret key is missing".to_string(),
+                    "Set destination.access_key_id and destination.secret_access_key, or AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY",
+                );
+            }
+            return None;
+        }
+        DestinationConfig::Webdav(settings) => {
+            if settings.username.is_empty() || settings.password.is_empty() {
+                report.fail(CHECK, "WebDAV username or password is empty".to_string(), "Set destination.username and destination.password (an app password with 2FA)");
+            } else {
+                report.pass(CHECK, format!("WebDAV credentials set for '{}'", settings.username));
+            }
+            return None;
+        }
+        DestinationConfig::Sftp(settings) => {
+            let key = config::resolve_path(&settings.private_key).unwrap_or_default();
+            let known_hosts = config::resolve_path(&settings.known_hosts).unwrap_or_default();
+            if !key.is_file() {
+                report.fail(CHECK, format!("SSH private key {:?} not found", key), "Fix destination.private_key");
+            } else if !known_hosts.is_file() {
+                report.fail(
+                    CHECK,
+                    format!("known_hosts file {:?} not found", known_hosts),
+                    &format!("Create it with: ssh-keyscan -p {} {} >> known_hosts", settings.port, settings.host),
+                );
+            } else {
+                report.pass(CHECK, format!("SSH key {:?} and known_hosts {:?} found", key, known_hosts));
+            }
+            return None;
+        }
+    }
+
+    let path = base_dir.join("credentials.json");
+    let key: serde_json::Value = match fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|json| {
+        serde_json::from_str(&json).map_err(|e| e.to_string())
+    }) {
+        Ok(key) => key,
+        Err(e) => {
+            report.fail(
+                CHECK,
+                format!("Cannot read {:?}: {}", path, e),
+                "Download a JSON key for the service account (IAM & Admin > Service Accounts > Keys) and save it as credentials.json next to the executable",
+            );
+            return None;
+        }
+    };
+
+    let field = |name: &str| key.get(name).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+    let key_type = field("type");
+    if key_type != "service_account" {
+        report.fail(
+            CHECK,
+            format!("credentials.json is of type '{}', not 'service_account'", key_type),
+            "OAuth client and user credentials are not supported; create a service account key instead",
+        );
+        return None;
+    }
+
+    let private_key = field("private_key");
+    let parsed = rustls_pemfile::private_key(&mut private_key.as_bytes());
+    let email = field("client_email");
+    match parsed {
+        Ok(Some(_)) if !email.is_empty() => report.pass(CHECK, format!("service account key for {}", email)),
+        _ => report.fail(
+            CHECK,
+            "credentials.json has no valid private_key or client_email".to_string(),
+            "The key file is damaged or truncated; create a new key for the service account",
+        ),
+    }
+
+    let token_uri = config.drive_api.token_uri.clone().unwrap_or_else(|| field("token_uri"));
+    Some(if token_uri.is_empty() { DEFAULT_TOKEN_URI.to_string() } else { token_uri })
+}
+
+// Reaches each HTTP endpoint through the configured proxy and CA roots, and
+// compares the local clock with the first server's Date header
+async fn check_network(report: &mut Report, config: &Config, token_uri: Option<String>) {
+    const CHECK: &str = "Network and TLS";
+    let mut endpoints = Vec::new();
+    match &config.destination {
+        DestinationConfig::Drive => {
+            endpoints.extend(token_uri);
+            endpoints.push(config.drive_api.base_url.clone().unwrap_or_else(|| DEFAULT_DRIVE_URL.to_string()));
+        }
+        DestinationConfig::S3(settings) => endpoints.push(
+            settings
+                .endpoint
+                .clone()
+                .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", settings.region)),
+        ),
+        DestinationConfig::Webdav(settings) => endpoints.push(settings.url.clone()),
+        DestinationConfig::Local { .. } | DestinationConfig::Sftp(_) => {}
+    }
+    if endpoints.is_empty() {
+        report.skip(CHECK, "the destination does not use HTTP");
+        report.skip("Clock", "no HTTP server to compare with");
+        return;
+    }
+
+    let connector = match network::connector(&config.network) {
+        Ok(connector) => connector,
+        Err(e) => return report.fail(CHECK, e, "Fix network.proxy.url or the files in network.ca_bundles"),
+    };
+    let client = hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+        .build::<_, Empty<Bytes>>(connector);
+
+    let mut server_date = None;
+    for url in &endpoints {
+        let request = match hyper::Request::get(url.as_str()).body(Empty::new()) {
+            Ok(request) => request,
+            Err(e) => {
+                report.fail(CHECK, format!("Invalid URL '{}': {}", url, e), "Fix the URL in config.json");
+                continue;
+            }
+        };
+        let response = tokio::time::timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS), client.request(request)).await;
+        match response {
+            Ok(Ok(response)) => {
+                report.pass(CHECK, format!("{} reachable (HTTP {})", url, response.status().as_u16()));
+                if server_date.is_none() {
+                    server_date = response
+                        .headers()
+                        .get(hyper::header::DATE)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| chrono::DateTime::parse_from_rfc2822(v).ok())
+                        .map(|date| (url.clone(), date));
+                }
+            }
+            Ok(Err(e)) => {
+                let detail = format!("{} unreachable: {}", url, error_chain(&e));
+                report.fail(CHECK, detail.clone(), network_hint(&detail, config));
+            }
+            Err(_) => report.fail(
+                CHECK,
+                format!("{} did not respond within {}s", url, REQUEST_TIMEOUT_SECS),
+                network_hint("timeout", config),
+            ),
+        }
+    }
+
+    match server_date {
+        Some((url, date)) => {
+            let skew = (chrono::Utc::now() - date.with_timezone(&chrono::Utc)).num_seconds();
+            let detail = format!("local clock is {}s {} {}", skew.abs(), if skew >= 0 { "ahead of" } else { "behind" }, url);
+            let hint = "Synchronise the clock (w32tm /resync) or fix the time zone; signed token requests fail on skewed clocks";
+            if skew.abs() >= CLOCK_SKEW_FAIL_SECS {
+                report.fail("Clock", detail, hint);
+            } else if skew.abs() >= CLOCK_SKEW_WARN_SECS {
+                report.warn("Clock", detail, hint);
+            } else {
+                report.pass("Clock", detail);
+            }
+        }
+        None => report.skip("Clock", "no server returned a Date header"),
+    }
+}
+
+fn network_hint(detail: &str, config: &Config) -> &'static str {
+    let detail = detail.to_lowercase();
+    if detail.contains("certificate") || detail.contains("unknownissuer") {
+        "The server certificate is not trusted; behind a TLS-inspecting proxy, add its root CA to network.ca_bundles"
+    } else if detail.contains("proxy") {
+        "Check network.proxy (URL, username, password) and that the proxy allows CONNECT to this host"
+    } else if config.network.proxy.is_none() {
+        "Check DNS and the firewall; if outbound traffic must use a proxy, configure network.proxy"
+    } else {
+        "Check DNS and the firewall, and whether this host should be listed in network.proxy.no_proxy"
+    }
+}
+
+// Connects the way the service does and runs its access check, then reads the quota
+async fn check_destination(report: &mut Report, base_dir: &Path, config: &Config) {
+    const CHECK: &str = "Destination access";
+    let connect = async {
+        let backend = storage::connect(base_dir, config).await?;
+        backend.check_access().await?;
+        let quota = backend.quota().await;
+        Ok::<_, String>((backend.describe(), quota))
+    };
+
+    let (description, quota) = match tokio::time::timeout(Duration::from_secs(DESTINATION_TIMEOUT_SECS), connect).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => {
+            report.fail(CHECK, e, destination_hint(config));
+            report.skip("Quota", "destination not accessible");
+            return;
+        }
+        Err(_) => {
+            report.fail(CHECK, format!("no result within {}s", DESTINATION_TIMEOUT_SECS), destination_hint(config));
+            report.skip("Quota", "destination not accessible");
+            return;
+        }
+    };
+    report.pass(CHECK, format!("{} exists and is writable", description));
+
+    match quota {
+        Ok(Some(quota)) => {
+            let percent = quota.usage as f64 * 100.0 / quota.limit as f64;
+            let detail = format!(
+                "{:.1}% used ({:.2} of {:.2} GiB, {:.2} GiB free)",
+                percent,
+                gib(quota.usage),
+                gib(quota.limit),
+                gib(quota.limit - quota.usage)
+            );
+            let hint = "Free up space or raise the storage limit; uploads fail once it is full";
+            if percent >= 99.0 {
+                report.fail("Quota", detail, hint);
+            } else if config.quota.warning_percent > 0 && percent >= config.quota.warning_percent as f64 {
+                report.warn("Quota", detail, hint);
+            } else {
+                report.pass("Quota", detail);
+            }
+        }
+        Ok(None) => report.skip("Quota", "the destination reports no storage limit"),
+        Err(e) => report.warn("Quota", e, "The quota could not be read; uploads are not affected"),
+    }
+}
+
+fn destination_hint(config: &Config) -> &'static str {
+    match config.destination {
+        DestinationConfig::Drive => {
+            "Share the Drive folder with the service account's client_email as Editor and check gdrive_folder_id"
+        }
+        DestinationConfig::Local { .. } => "Check destination.path and that the service account can write there",
+        DestinationConfig::S3(_) => "Check the bucket name, endpoint, region, path_style and that the key may write to the bucket",
+        DestinationConfig::Webdav(_) => "Check destination.url (the collection must exist) and the credentials",
+        DestinationConfig::Sftp(_) => "Check host, port, username, key and known_hosts, and that remote_dir is writable",
+    }
+}
+
+// Reports the state the running service last recorded
+fn check_service_status(report: &mut Report, base_dir: &Path) {
+    const CHECK: &str = "Service status";
+    let status: serde_json::Value = match fs::read_to_string(base_dir.join("service_status.json"))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+    {
+        Some(status) => status,
+        None => return report.skip(CHECK, "the service has not recorded a status yet"),
+    };
+    let field = |name: &str| status.get(name).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+    let detail = format!("{} since {}", field("state"), field("since"));
+    if field("state") == "degraded" {
+        report.warn(
+            CHECK,
+            format!("{}; {} check failed: {}", detail, field("failed_check"), field("last_error")),
+            "Fix the failing check above; the service retries on its own",
+        );
+    } else {
+        report.pass(CHECK, detail);
+    }
+}
+
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    message
+}
+
+fn gib(bytes: i64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0 * 1024.0)
+}
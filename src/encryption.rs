@@ -0,0 +1,359 @@
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::stream::{DecryptorBE32, EncryptorBE32};
+use aes_gcm::aead::{OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key};
+use hmac::{Hmac, Mac};
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, EncryptionConfig};
+
+// Extension appended to the remote name of encrypted uploads
+pub const ENCRYPTED_EXTENSION: &str = "enc";
+
+// File layout: header, then the plaintext in CHUNK_SIZE pieces, each sealed with
+// AES-256-GCM using the STREAM construction (big-endian 32-bit counter + last-chunk
+// flag), so truncation, reordering and tampering are all detected on decrypt.
+//
+// Header: magic (8) | kdf (1) | pbkdf2 rounds, BE (4) | salt (16) | nonce prefix (7)
+const MAGIC: &[u8; 8] = b"GDSENC01";
+const KDF_RAW_KEY: u8 = 0;
+const KDF_PBKDF2_SHA256: u8 = 1;
+const PBKDF2_ROUNDS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 7;
+const HEADER_LEN: usize = 8 + 1 + 4 + SALT_LEN + NONCE_LEN;
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+const NAME_KEY_CONTEXT: &[u8] = b"gdrive-stealth-sync remote names v1";
+
+pub enum KeyMaterial {
+    Key([u8; 32]),
+    Passphrase(Vec<u8>),
+}
+
+impl KeyMaterial {
+    // A key file holds 64 hex characters or exactly 32 raw bytes
+    pub fn from_key_file(path: &Path) -> Result<Self, String> {
+        let content = fs::read(path).map_err(|e| format!("Failed to read key file {:?}: {}", path, e))?;
+        let text = String::from_utf8_lossy(&content);
+        let hex = text.trim();
+        if hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            let mut key = [0u8; 32];
+            for (i, byte) in key.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                    .map_err(|e| format!("Invalid key file {:?}: {}", path, e))?;
+            }
+            return Ok(KeyMaterial::Key(key));
+        }
+        let key: [u8; 32] = content
+            .as_slice()
+            .try_into()
+            .map_err(|_| format!("Key file {:?} must contain 32 bytes or 64 hex characters", path))?;
+        Ok(KeyMaterial::Key(key))
+    }
+
+    // Trailing newlines are not part of the passphrase
+    pub fn from_passphrase_file(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read passphrase file {:?}: {}", path, e))?;
+        let passphrase = content.trim_end_matches(['\r', '\n']);
+        if passphrase.is_empty() {
+            return Err(format!("Passphrase file {:?} is empty", path));
+        }
+        Ok(KeyMaterial::Passphrase(passphrase.as_bytes().to_vec()))
+    }
+
+    pub fn from_config(settings: &EncryptionConfig) -> Result<Self, String> {
+        match (&settings.key_file, &settings.passphrase_file) {
+            (Some(key_file), None) => Self::from_key_file(&config::resolve_path(key_file)?),
+            (None, Some(passphrase_file)) => Self::from_passphrase_file(&config::resolve_path(passphrase_file)?),
+            (Some(_), Some(_)) => Err("Set only one of encryption.key_file and encryption.passphrase_file".to_string()),
+            (None, None) => Err("Encryption is enabled but neither key_file nor passphrase_file is set".to_string()),
+        }
+    }
+
+    // Separate key for deterministic remote names, so name hashes reveal nothing
+    // about the content key. Derived once at startup.
+    pub fn derive_name_key(&self) -> [u8; 32] {
+        match self {
+            KeyMaterial::Key(key) => {
+                let mut mac = <Hmac<sha2::Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+                mac.update(NAME_KEY_CONTEXT);
+                mac.finalize().into_bytes().into()
+            }
+            KeyMaterial::Passphrase(passphrase) => {
+                pbkdf2::pbkdf2_hmac_array::<sha2::Sha256, 32>(passphrase, NAME_KEY_CONTEXT, PBKDF2_ROUNDS)
+            }
+        }
+    }
+
+    fn derive(&self, kdf: u8, rounds: u32, salt: &[u8]) -> Result<[u8; 32], String> {
+        match (self, kdf) {
+            (KeyMaterial::Key(key), KDF_RAW_KEY) => Ok(*key),
+            (KeyMaterial::Passphrase(passphrase), KDF_PBKDF2_SHA256) => {
+                Ok(pbkdf2::pbkdf2_hmac_array::<sha2::Sha256, 32>(passphrase, salt, rounds))
+            }
+            (KeyMaterial::Key(_), _) => Err("File was encrypted with a passphrase, not a key file".to_string()),
+            (KeyMaterial::Passphrase(_), _) => Err("File was encrypted with a key file, not a passphrase".to_string()),
+        }
+    }
+}
+
+// Ciphertext staged for upload; removed again when dropped
+pub struct EncryptedCopy {
+    pub path: PathBuf,
+}
+
+impl Drop for EncryptedCopy {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Encrypts `input` into the staging folder next to the executable
+pub fn encrypt_to_staging(input: &Path, key: &KeyMaterial) -> Result<EncryptedCopy, String> {
+    let staging_dir = config::base_dir()?.join("staging");
+    fs::create_dir_all(&staging_dir)
+        .map_err(|e| format!("Failed to create staging folder {:?}: {}", staging_dir, e))?;
+
+    let file_name = input
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| format!("Failed to get file name from path: {:?}", input))?;
+    let staged = EncryptedCopy {
+        path: staging_dir.join(format!("{}.{}.{}", std::process::id(), file_name, ENCRYPTED_EXTENSION)),
+    };
+    encrypt_file(input, &staged.path, key)?;
+    Ok(staged)
+}
+
+// Size of the encrypted form of `plain_len` bytes: the header plus one tag per
+// chunk (an empty file still gets one sealed chunk)
+pub fn encrypted_size(plain_len: u64) -> u64 {
+    let chunks = plain_len.div_ceil(CHUNK_SIZE as u64).max(1);
+    HEADER_LEN as u64 + plain_len + chunks * TAG_LEN as u64
+}
+
+pub fn encrypt_file(input: &Path, output: &Path, key: &KeyMaterial) -> Result<(), String> {
+    let reader = File::open(input).map_err(|e| format!("Failed to open {:?}: {}", input, e))?;
+    let out_file = File::create(output).map_err(|e| format!("Failed to create {:?}: {}", output, e))?;
+    encrypt_stream(reader, BufWriter::new(out_file), key)
+        .map_err(|e| format!("Failed to encrypt {:?}: {}", input, e))
+}
+
+// Decrypts into a sibling ".partial" file that replaces `output` only once the
+// whole file has been authenticated, so an existing file at `output` survives
+// a wrong key or an input that is not ours
+pub fn decrypt_file(input: &Path, output: &Path, key: &KeyMaterial) -> Result<(), String> {
+    let mut reader = File::open(input).map_err(|e| format!("Failed to open {:?}: {}", input, e))?;
+    let header = read_chunk(&mut reader, HEADER_LEN)?;
+    check_header(&header).map_err(|e| format!("Failed to decrypt {:?}: {}", input, e))?;
+
+    let file_name = output
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| format!("Failed to get file name from path: {:?}", output))?;
+    let partial = output.with_file_name(format!("{}.partial", file_name));
+    let out_file = File::create(&partial).map_err(|e| format!("Failed to create {:?}: {}", partial, e))?;
+    let result = decrypt_stream(header.as_slice().chain(reader), BufWriter::new(out_file), key)
+        .map_err(|e| format!("Failed to decrypt {:?}: {}", input, e))
+        .and_then(|_| {
+            fs::rename(&partial, output).map_err(|e| format!("Failed to move {:?} to {:?}: {}", partial, output, e))
+        });
+    if result.is_err() {
+        // Never leave a partially decrypted file behind
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+pub fn encrypt_stream(mut reader: impl Read, mut writer: impl Write, key: &KeyMaterial) -> Result<(), String> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let (kdf, rounds) = match key {
+        KeyMaterial::Key(_) => (KDF_RAW_KEY, 0),
+        KeyMaterial::Passphrase(_) => {
+            OsRng.fill_bytes(&mut salt);
+            (KDF_PBKDF2_SHA256, PBKDF2_ROUNDS)
+        }
+    };
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.push(kdf);
+    header.extend_from_slice(&rounds.to_be_bytes());
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&nonce);
+
+    let cipher_key = key.derive(kdf, rounds, &salt)?;
+    let mut encryptor = EncryptorBE32::<Aes256Gcm>::new(
+        Key::<Aes256Gcm>::from_slice(&cipher_key),
+        GenericArray::from_slice(&nonce),
+    );
+
+    writer.write_all(&header).map_err(|e| e.to_string())?;
+
+    // One chunk of look-ahead so the final chunk can be sealed as "last"
+    let mut current = read_chunk(&mut reader, CHUNK_SIZE)?;
+    loop {
+        let next = read_chunk(&mut reader, CHUNK_SIZE)?;
+        let payload = Payload { msg: &current, aad: &header };
+        if next.is_empty() {
+            let sealed = encryptor.encrypt_last(payload).map_err(|_| "encryption failed".to_string())?;
+            writer.write_all(&sealed).map_err(|e| e.to_string())?;
+            break;
+        }
+        let sealed = encryptor.encrypt_next(payload).map_err(|_| "encryption failed".to_string())?;
+        writer.write_all(&sealed).map_err(|e| e.to_string())?;
+        current = next;
+    }
+
+    writer.flush().map_err(|e| e.to_string())
+}
+
+pub fn decrypt_stream(mut reader: impl Read, mut writer: impl Write, key: &KeyMaterial) -> Result<(), String> {
+    let header = read_chunk(&mut reader, HEADER_LEN)?;
+    check_header(&header)?;
+    let kdf = header[8];
+    let rounds = u32::from_be_bytes([header[9], header[10], header[11], header[12]]);
+    let salt = &header[13..13 + SALT_LEN];
+    let nonce = &header[13 + SALT_LEN..HEADER_LEN];
+
+    let cipher_key = key.derive(kdf, rounds, salt)?;
+    let mut decryptor = DecryptorBE32::<Aes256Gcm>::new(
+        Key::<Aes256Gcm>::from_slice(&cipher_key),
+        GenericArray::from_slice(nonce),
+    );
+    let auth_err = || "data is corrupted or the key is wrong".to_string();
+
+    let mut current = read_chunk(&mut reader, CHUNK_SIZE + TAG_LEN)?;
+    loop {
+        let next = read_chunk(&mut reader, CHUNK_SIZE + TAG_LEN)?;
+        let payload = Payload { msg: &current, aad: &header };
+        if next.is_empty() {
+            let plain = decryptor.decrypt_last(payload).map_err(|_| auth_err())?;
+            writer.write_all(&plain).map_err(|e| e.to_string())?;
+            break;
+        }
+        let plain = decryptor.decrypt_next(payload).map_err(|_| auth_err())?;
+        writer.write_all(&plain).map_err(|e| e.to_string())?;
+        current = next;
+    }
+
+    writer.flush().map_err(|e| e.to_string())
+}
+
+// The round count is only ever the one this version writes: it comes from the
+// file, and an arbitrary value could pin a CPU for hours (or give a trivial key)
+fn check_header(header: &[u8]) -> Result<(), String> {
+    if header.len() != HEADER_LEN || &header[..8] != MAGIC {
+        return Err("not an encrypted file".to_string());
+    }
+    let rounds = u32::from_be_bytes([header[9], header[10], header[11], header[12]]);
+    match (header[8], rounds) {
+        (KDF_RAW_KEY, 0) | (KDF_PBKDF2_SHA256, PBKDF2_ROUNDS) => Ok(()),
+        (KDF_RAW_KEY | KDF_PBKDF2_SHA256, _) => Err(format!("unsupported key derivation round count {}", rounds)),
+        (kdf, _) => Err(format!("unknown key derivation method {}", kdf)),
+    }
+}
+
+// Reads up to `size` bytes, only returning less at end of file
+fn read_chunk(reader: &mut impl Read, size: usize) -> Result<Vec<u8>, String> {
+    let mut buffer = vec![0u8; size];
+    let mut filled = 0;
+    while filled < size {
+        let read = reader.read(&mut buffer[filled..]).map_err(|e| e.to_string())?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    buffer.truncate(filled);
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn round_trip_restores_the_plaintext() {
+        let dir = temp_dir("encryption-round-trip");
+        let plain = dir.join("report.txt");
+        let plaintext: Vec<u8> = (0..CHUNK_SIZE * 2 + 123).map(|i| i as u8).collect();
+        fs::write(&plain, &plaintext).unwrap();
+
+        let key = KeyMaterial::Key([7u8; 32]);
+        encrypt_file(&plain, &dir.join("report.txt.enc"), &key).unwrap();
+        assert_eq!(
+            fs::metadata(dir.join("report.txt.enc")).unwrap().len(),
+            encrypted_size(plaintext.len() as u64)
+        );
+        decrypt_file(&dir.join("report.txt.enc"), &dir.join("restored.txt"), &key).unwrap();
+
+        assert_eq!(fs::read(dir.join("restored.txt")).unwrap(), plaintext);
+        assert!(!dir.join("restored.txt.partial").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn foreign_input_leaves_an_existing_output_alone() {
+        let dir = temp_dir("encryption-foreign");
+        fs::write(dir.join("notes.enc"), b"not one of ours").unwrap();
+        fs::write(dir.join("notes"), b"precious plaintext").unwrap();
+
+        let result = decrypt_file(&dir.join("notes.enc"), &dir.join("notes"), &KeyMaterial::Key([1u8; 32]));
+
+        assert!(result.unwrap_err().contains("not an encrypted file"));
+        assert_eq!(fs::read(dir.join("notes")).unwrap(), b"precious plaintext");
+        assert!(!dir.join("notes.partial").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn wrong_key_leaves_an_existing_output_alone() {
+        let dir = temp_dir("encryption-wrong-key");
+        fs::write(dir.join("notes"), b"precious plaintext").unwrap();
+        encrypt_file(&dir.join("notes"), &dir.join("notes.enc"), &KeyMaterial::Key([1u8; 32])).unwrap();
+
+        let result = decrypt_file(&dir.join("notes.enc"), &dir.join("notes"), &KeyMaterial::Key([2u8; 32]));
+
+        assert!(result.unwrap_err().contains("key is wrong"));
+        assert_eq!(fs::read(dir.join("notes")).unwrap(), b"precious plaintext");
+        assert!(!dir.join("notes.partial").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_headers_with_other_round_counts() {
+        // Checked before any key is derived, so no PBKDF2 runs here
+        let header = |kdf: u8, rounds: u32| {
+            let mut header = MAGIC.to_vec();
+            header.push(kdf);
+            header.extend_from_slice(&rounds.to_be_bytes());
+            header.extend_from_slice(&[0u8; SALT_LEN + NONCE_LEN + TAG_LEN]);
+            header
+        };
+        let passphrase = KeyMaterial::Passphrase(b"correct horse".to_vec());
+        for rounds in [0, 1, PBKDF2_ROUNDS - 1, PBKDF2_ROUNDS + 1, u32::MAX] {
+            let error = decrypt_stream(header(KDF_PBKDF2_SHA256, rounds).as_slice(), Vec::new(), &passphrase).unwrap_err();
+            assert_eq!(error, format!("unsupported key derivation round count {}", rounds));
+        }
+        let key = KeyMaterial::Key([5u8; 32]);
+        assert!(decrypt_stream(header(KDF_RAW_KEY, 1000).as_slice(), Vec::new(), &key).unwrap_err().contains("round count"));
+        assert!(decrypt_stream(header(9, 0).as_slice(), Vec::new(), &key).unwrap_err().contains("unknown key derivation"));
+
+        // What this version writes is accepted
+        assert!(check_header(&header(KDF_PBKDF2_SHA256, PBKDF2_ROUNDS)[..HEADER_LEN]).is_ok());
+        let mut encrypted = Vec::new();
+        encrypt_stream(&b"payroll"[..], &mut encrypted, &key).unwrap();
+        let mut plain = Vec::new();
+        decrypt_stream(encrypted.as_slice(), &mut plain, &key).unwrap();
+        assert_eq!(plain, b"payroll");
+    }
+}
@@ -1,119 +1,589 @@
-use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
-use notify_debouncer_full::{new_debouncer, notify::*};
-use google_drive3::{api, DriveHub, hyper_util::client::legacy::connect::HttpConnector, yup_oauth2::ServiceAccountKey};
-use hyper_rustls::HttpsConnector;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use notify_debouncer_full::notify::{self, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use notify_debouncer_full::{new_debouncer, new_debouncer_opt, DebounceEventResult, Debouncer, FileIdMap};
 
 // Import the logging functions from main.rs
 use crate::{log_info, log_error}; 
+use crate::config::{self, AfterUploadAction, Config, NameConflictPolicy, RenameSuffix, WatchBackend, WatchConfig, WebhookEvent};
+use crate::audit_log::{self, AuditEntry};
+use crate::checksum;
+use crate::post_upload;
+use crate::encryption::{self, KeyMaterial};
+use crate::local_files::{self, EntryKind};
+use crate::name_manifest::{self, NameManifest};
+use crate::sync_state::{self, SyncRecord, SyncState};
+use crate::alerts;
+#[cfg(windows)]
+use crate::health;
+use crate::health::{HealthCheck, StartupFailure};
+use crate::storage::{self, StorageBackend, UploadMetadata};
+use crate::webhooks::{self, BatchDetails, FileDetails, Notification, QuotaDetails};
 
-#[derive(Deserialize)]
-struct Config {
-    local_folder_path: String,
-    gdrive_folder_id: String,
+struct EncryptionContext {
+    key: KeyMaterial,
+    // Present when remote names are encrypted as well as contents
+    manifest: Option<NameManifest>,
 }
 
+// Everything an upload needs, built once by the sync loop
+struct SyncContext {
+    backend: Box<dyn StorageBackend>,
+    config: Config,
+    encryption: Option<EncryptionContext>,
+    state: SyncState,
+    // Set for a dry run: uploads are planned and tallied here instead of made
+    dry_run: Option<DryRunPlan>,
+    last_quota_check: Option<Instant>,
+    // Storage use was above the warning level at the last check
+    quota_warned: bool,
+}
+
+// What a dry run would have done
+#[derive(Default)]
+pub struct DryRunPlan {
+    pub creates: usize,
+    pub updates: usize,
+    pub skips: usize,
+    pub local_deletes: usize,
+    pub local_moves: usize,
+    pub errors: usize,
+    // Bytes that would be sent, the ciphertext size when encrypting
+    pub bytes: u64,
+}
+
+impl DryRunPlan {
+    pub fn summary(&self) -> String {
+        format!(
+            "Dry run summary: {} file(s) to create, {} to update, {} to skip ({} bytes to upload); \
+             {} local file(s) to delete, {} to move to the archive; {} error(s)",
+            self.creates, self.updates, self.skips, self.bytes, self.local_deletes, self.local_moves, self.errors
+        )
+    }
+}
+
+// Why the folder is being compared with the sync state
+#[derive(Clone, Copy, PartialEq)]
+enum ScanKind {
+    Initial,
+    Periodic,
+    // The watcher reported an error, so events may have been lost
+    AfterWatchError,
+    // The folder came back after being unavailable
+    CatchUp,
+}
+
+impl ScanKind {
+    fn label(self) -> &'static str {
+        match self {
+            ScanKind::Initial => "Initial sync",
+            ScanKind::Periodic => "Periodic rescan",
+            ScanKind::AfterWatchError => "Rescan after watch error",
+            ScanKind::CatchUp => "Catch-up rescan",
+        }
+    }
+}
+
+// How often the watch loop checks that the folder is still there, and the
+// longest wait between checks while it is missing
+const FOLDER_CHECK_SECS: u64 = 10;
+const FOLDER_RETRY_MAX_SECS: u64 = 300;
+// Files uploaded by the watcher are added to the remote name manifest in one
+// upload at most this long after the first of them
+const MANIFEST_FLUSH_SECS: u64 = 60;
+
+// Files modified more recently than this are left to the watcher during rescans,
+// since they may still be being written
+const RESCAN_SETTLE_SECS: i64 = 10;
+
+// Result of handing one file to upload_file
+enum UploadOutcome {
+    Uploaded,
+    // Left alone because of the name conflict policy
+    Skipped,
+    // Gave up, either before uploading or after the last retry
+    Failed,
+    // Recorded in the dry run plan
+    Planned,
+    // Not a regular file, or excluded by the symlink policy
+    Ignored,
+}
+
+// Counts from one reconciliation pass
+#[derive(Default)]
+pub struct SyncSummary {
+    pub up_to_date: usize,
+    pub uploaded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+impl SyncSummary {
+    pub fn summary(&self) -> String {
+        format!(
+            "Sync summary: {} uploaded, {} skipped, {} failed, {} already up to date",
+            self.uploaded, self.skipped, self.failed, self.up_to_date
+        )
+    }
+}
+
+// Runs the startup checks until they pass, backing off between attempts and
+// reporting the service as degraded meanwhile, then syncs until stopped
+#[cfg(windows)]
 pub async fn run_sync_loop() {
-    let exe_path = match std::env::current_exe() {
-        Ok(path) => path,
-        Err(e) => {
-            log_error(&format!("Failed to get current executable path: {}", e));
-            return;
+    health::report_starting();
+
+    let mut failures = 0;
+    let mut ctx = loop {
+        match build_context(false).await {
+            Ok(ctx) => break ctx,
+            Err(failure) => {
+                failures += 1;
+                let delay = supervisor_delay(failures);
+                log_error(&format!(
+                    "Startup check '{}' failed (attempt {}): {}. Retrying in {}s",
+                    failure.check.label(), failures, failure.message, delay.as_secs()
+                ));
+                health::report_degraded(&failure, failures, delay);
+                if failures == 1 {
+                    notify(Notification::new(
+                        WebhookEvent::ServiceDegraded,
+                        &format!("Service degraded: {} check failed: {}", failure.check.label(), failure.message),
+                    ));
+                }
+                tokio::time::sleep(delay).await;
+            }
         }
     };
-    let base_dir = match exe_path.parent() {
-        Some(dir) => dir,
-        None => {
-            log_error("Failed to get parent directory of executable");
-            return;
+
+    if failures > 0 {
+        log_info(&format!("Startup checks passed after {} failed attempt(s)", failures));
+    }
+    health::report_healthy();
+
+    notify(Notification::new(
+        WebhookEvent::ServiceStarted,
+        &format!("Service started, watching '{}'", ctx.config.local_folder_path),
+    ));
+
+    watch_until_stopped(&mut ctx, None).await;
+}
+
+// Same pipeline as the service, including read-only destination queries for name
+// conflicts, but nothing is uploaded, trashed or changed locally. Runs until
+// `stop` is set and returns what would have been done.
+pub async fn run_dry_run(stop: &AtomicBool) -> Result<DryRunPlan, String> {
+    let mut ctx = build_context(true).await?;
+    log_info("Dry run: no files will be uploaded, changed or deleted");
+
+    watch_until_stopped(&mut ctx, Some(stop)).await;
+
+    Ok(ctx.dry_run.unwrap_or_default())
+}
+
+// One reconciliation pass with the usual retries, then return instead of watching
+pub async fn run_sync_once() -> Result<SyncSummary, String> {
+    let mut ctx = build_context(false).await?;
+    let summary = reconcile(&mut ctx, ScanKind::Initial).await;
+    webhooks::flush(Duration::from_secs(60)).await;
+    alerts::flush().await;
+    Ok(summary)
+}
+
+// Loads the configuration and runs the startup checks: settings, watched
+// folder, destination credentials and access to the destination folder
+async fn build_context(dry_run: bool) -> Result<SyncContext, StartupFailure> {
+    let base_dir = config::base_dir().map_err(|e| HealthCheck::Config.failed(e))?;
+    let config = config::load_config().map_err(|e| HealthCheck::Config.failed(e))?;
+
+    // A dry run leaves no audit records behind
+    if config.audit.enabled && !dry_run {
+        let audit_dir = config.audit.audit_dir().map_err(|e| HealthCheck::Config.failed(e))?;
+        audit_log::init_audit_log(audit_dir.clone(), &config.audit).map_err(|e| {
+            HealthCheck::Config.failed(format!("Failed to open audit log in {:?}: {}", audit_dir, e))
+        })?;
+        log_info(&format!("Upload audit trail is written to {:?}", audit_dir));
+    }
+
+    if !config.webhooks.is_empty() && !dry_run {
+        webhooks::init_webhooks(&config.webhooks, &config.network).map_err(|e| HealthCheck::Config.failed(e))?;
+        log_info(&format!("Sending notifications to {} webhook(s)", config.webhooks.len()));
+    }
+
+    if let Some(email) = config.alerts.email.as_ref().filter(|_| !dry_run) {
+        alerts::init_email_alerts(email).map_err(|e| HealthCheck::Config.failed(e))?;
+        log_info(&format!("Email alerts go to {} via {}:{}", email.to.join(", "), email.host, email.port));
+    }
+
+    // Load the key once so a bad key file stops the service instead of every upload
+    let mut encryption = if config.encryption.enabled {
+        let key = KeyMaterial::from_config(&config.encryption).map_err(|e| HealthCheck::Config.failed(e))?;
+        Some(EncryptionContext { key, manifest: None })
+    } else {
+        None
+    };
+
+    if !Path::new(&config.local_folder_path).is_dir() {
+        return Err(HealthCheck::LocalFolder.failed(format!(
+            "Watched folder '{}' does not exist or is not a folder",
+            config.local_folder_path
+        )));
+    }
+
+    let backend = storage::connect(&base_dir, &config)
+        .await
+        .map_err(|e| HealthCheck::Destination.failed(e))?;
+
+    // Writes a probe on some destinations, so a dry run skips it
+    if !dry_run {
+        backend
+            .check_access()
+            .await
+            .map_err(|e| HealthCheck::DestinationAccess.failed(e))?;
+    }
+
+    let state = SyncState::load(base_dir.join("sync_state.json")).map_err(|e| HealthCheck::Config.failed(e))?;
+
+    if let Some(ctx) = encryption.as_mut() {
+        if config.encryption.encrypt_names {
+            let manifest = open_name_manifest(backend.as_ref(), &base_dir, &ctx.key, &state)
+                .await
+                .map_err(|e| HealthCheck::Destination.failed(e))?;
+            ctx.manifest = Some(manifest);
+        }
+    }
+
+    let dry_run = if dry_run { Some(DryRunPlan::default()) } else { None };
+    Ok(SyncContext {
+        backend,
+        config,
+        encryption,
+        state,
+        dry_run,
+        last_quota_check: None,
+        quota_warned: false,
+    })
+}
+
+// Uploads files in the folder that the sync state has no current record for:
+// files added while the service was stopped or missed by the watcher, changed
+// files and earlier failures
+async fn reconcile(ctx: &mut SyncContext, kind: ScanKind) -> SyncSummary {
+    let mut summary = SyncSummary::default();
+    let folder = PathBuf::from(&ctx.config.local_folder_path);
+    log_info(&format!("{} starting for folder '{}'", kind.label(), folder.display()));
+    let settle_cutoff = chrono::Utc::now().timestamp() - RESCAN_SETTLE_SECS;
+
+    let entries = match fs::read_dir(&folder) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log_error(&format!("Failed to list folder '{}': {}", folder.display(), e));
+            summary.failed += 1;
+            return summary;
         }
     };
 
-    let config_path = base_dir.join("config.json");
-    let config: Config = match fs::read_to_string(&config_path) {
-        Ok(s) => match serde_json::from_str(&s) {
-            Ok(cfg) => cfg,
+    let mut pending = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match local_files::classify(&path, &folder, ctx.config.symlinks) {
+            EntryKind::File => {}
+            EntryKind::Skipped(reason) => {
+                // Already reported by earlier passes; rescans would repeat it every time
+                if matches!(kind, ScanKind::Periodic | ScanKind::AfterWatchError) {
+                    log::debug!("Skipping {:?}: {}", path, reason);
+                } else {
+                    log_info(&format!("Skipping {:?}: {}", path, reason));
+                }
+                continue;
+            }
+            EntryKind::Directory | EntryKind::Missing => continue,
+        }
+        if ctx.state.is_up_to_date(&relative_path(&ctx.config, &path), &path) {
+            summary.up_to_date += 1;
+        } else if kind != ScanKind::Initial && sync_state::modified_secs(&path) > settle_cutoff {
+            continue;
+        } else {
+            pending.push(path);
+        }
+    }
+    pending.sort();
+    if ctx.state.is_empty() && !pending.is_empty() {
+        let found = pending.len();
+        pending = match adopt_existing_copies(ctx, pending).await {
+            Ok(rest) => rest,
             Err(e) => {
-                log_error(&format!("Failed to parse config.json: {}", e));
-                return;
+                // Uploading without the comparison would duplicate what is already there
+                log_error(&format!("Failed to compare with files already at the destination; retrying on the next scan: {}", e));
+                summary.failed += found;
+                return summary;
             }
-        },
-        Err(e) => {
-            log_error(&format!("Failed to read config.json: {}", e));
-            return;
+        };
+        summary.up_to_date += found - pending.len();
+    }
+
+    log_info(&format!(
+        "{} found {} file(s) to upload and {} already up to date",
+        kind.label(), pending.len(), summary.up_to_date
+    ));
+    for path in pending {
+        match upload_file(ctx, &path).await {
+            UploadOutcome::Uploaded => summary.uploaded += 1,
+            UploadOutcome::Skipped => summary.skipped += 1,
+            UploadOutcome::Failed => summary.failed += 1,
+            UploadOutcome::Planned | UploadOutcome::Ignored => {}
         }
-    };
+    }
 
-    let creds_path = base_dir.join("credentials.json");
-    let secret_json = match fs::read_to_string(&creds_path) {
-        Ok(content) => content,
-        Err(e) => {
-            log_error(&format!("Failed to read credentials.json: {}", e));
-            return;
+    flush_name_manifest(ctx).await;
+
+    if summary.uploaded + summary.failed > 0 {
+        let mut notification = Notification::new(
+            WebhookEvent::SyncBatch,
+            &format!("{}: {}", kind.label(), summary.summary()),
+        );
+        notification.batch = Some(BatchDetails {
+            scan: kind.label().to_string(),
+            uploaded: summary.uploaded,
+            skipped: summary.skipped,
+            failed: summary.failed,
+            up_to_date: summary.up_to_date,
+        });
+        notify(notification);
+    }
+    summary
+}
+
+// Without any sync state (first start, or the first after upgrading from a
+// version that kept none) the destination may already hold copies of the
+// folder. Files with a copy of the same name and size, and MD5 where the
+// destination reports one, are recorded instead of uploaded again; the rest
+// are returned for uploading.
+async fn adopt_existing_copies(ctx: &mut SyncContext, pending: Vec<PathBuf>) -> Result<Vec<PathBuf>, String> {
+    // Encrypted uploads get new names, so there is nothing to match them with
+    if ctx.encryption.is_some() {
+        return Ok(pending);
+    }
+    let backend = ctx.backend.as_ref();
+    let mut remote: HashMap<String, Vec<storage::RemoteFile>> = HashMap::new();
+    for file in backend.list(backend.root_folder(), None).await? {
+        remote.entry(file.name.clone()).or_default().push(file);
+    }
+
+    let mut rest = Vec::new();
+    let mut adopted = 0;
+    for path in pending {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        let size = fs::metadata(&path).map(|m| m.len()).ok();
+        let candidates: Vec<&storage::RemoteFile> = remote
+            .get(name)
+            .into_iter()
+            .flatten()
+            .filter(|file| size.is_some() && file.size == size)
+            .collect();
+        if candidates.is_empty() {
+            rest.push(path);
+            continue;
         }
-    };
+        let local_md5 = match checksum::md5_file(&path) {
+            Ok(md5) => md5,
+            Err(e) => {
+                log_error(&format!("Failed to hash file {:?}: {}", path, e));
+                rest.push(path);
+                continue;
+            }
+        };
+        let mut existing = None;
+        for candidate in candidates {
+            // Listings may leave out checksums the destination reports per file
+            let md5 = match &candidate.md5 {
+                Some(md5) => Some(md5.clone()),
+                None => backend.metadata(&candidate.id).await?.md5,
+            };
+            if md5.is_none_or(|md5| md5 == local_md5) {
+                existing = Some(candidate);
+                break;
+            }
+        }
+        let existing = match existing {
+            Some(existing) => existing,
+            None => {
+                rest.push(path);
+                continue;
+            }
+        };
 
-    let secret: ServiceAccountKey = match serde_json::from_str(&secret_json) {
-        Ok(key) => key,
-        Err(e) => {
-            log_error(&format!("Failed to parse service account key: {}", e));
-            return;
+        adopted += 1;
+        if ctx.dry_run.is_some() {
+            log_info(&format!("[dry run] Would record '{}' as already uploaded ({})", name, existing.id));
+            continue;
         }
-    };
+        log::debug!("'{}' is already at the destination as {}; recording it instead of uploading", name, existing.id);
+        let record = SyncRecord {
+            size: existing.size.unwrap_or_default(),
+            modified: sync_state::modified_secs(&path),
+            md5: local_md5,
+            drive_file_id: existing.id.clone(),
+            remote_name: existing.name.clone(),
+            verified: true,
+            uploaded_at: chrono::Utc::now().to_rfc3339(),
+        };
+        ctx.state.insert(&relative_path(&ctx.config, &path), record)?;
+    }
+    if adopted > 0 {
+        log_info(&format!("{} file(s) were already at {} and are recorded as uploaded", adopted, backend.describe()));
+    }
+    Ok(rest)
+}
 
-    let auth = match google_drive3::yup_oauth2::ServiceAccountAuthenticator::builder(secret)
-        .build()
-        .await
-    {
-        Ok(auth) => auth,
-        Err(e) => {
-            log_error(&format!("Failed to create authenticator: {}", e));
+// Why watch_folder returned
+enum WatchEnd {
+    Stopped,
+    // The folder disappeared (unplugged drive, share gone offline)
+    FolderLost,
+}
+
+// Runs the initial sync and the watch, and keeps the watch going across the
+// folder disappearing: waits with backoff for it to return, then re-establishes
+// the watch and catches up with a rescan
+async fn watch_until_stopped(ctx: &mut SyncContext, stop: Option<&AtomicBool>) {
+    let mut kind = ScanKind::Initial;
+    let mut failures = 0;
+    loop {
+        if !wait_for_folder(ctx, stop).await {
             return;
         }
-    };
 
-    // Create HTTP client using the newer API structure
-    let https_connector = match hyper_rustls::HttpsConnectorBuilder::new()
-        .with_native_roots()
-    {
-        Ok(builder) => builder.https_or_http().enable_http1().build(),
-        Err(e) => {
-            log_error(&format!("Failed to build HTTPS connector: {}", e));
-            return;
+        // A dry run plans the initial sync only; later passes would count the same files again
+        if kind == ScanKind::Initial || ctx.dry_run.is_none() {
+            let summary = reconcile(ctx, kind).await;
+            if ctx.dry_run.is_none() {
+                log_info(&summary.summary());
+            }
         }
-    };
-    
-    let client = hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
-        .build(https_connector);
+        kind = ScanKind::CatchUp;
 
-    let hub = DriveHub::new(client, auth);
+        match watch_folder(ctx, stop).await {
+            Ok(WatchEnd::Stopped) => return,
+            Ok(WatchEnd::FolderLost) => failures = 0,
+            Err(e) => {
+                log_error(&e);
+                failures += 1;
+                let delay = folder_retry_delay(failures);
+                log_info(&format!("Retrying the watch in {}s", delay.as_secs()));
+                if !sleep_unless_stopped(delay, stop).await {
+                    return;
+                }
+            }
+        }
+    }
+}
 
-    log_info(&format!("Google Drive connection established successfully"));
-    log_info(&format!("Initial sync starting for folder '{}'", &config.local_folder_path));
-    // TODO: Implement an initial sync for existing files.
+// Returns once the folder exists, or false if stopped while waiting
+async fn wait_for_folder(ctx: &SyncContext, stop: Option<&AtomicBool>) -> bool {
+    let folder = Path::new(&ctx.config.local_folder_path);
+    if folder.is_dir() {
+        return true;
+    }
 
-    log_info(&format!("Now watching for new files in: {}", &config.local_folder_path));
-    log_info(&format!("Target Google Drive folder ID: {}", &config.gdrive_folder_id));
+    log_error(&format!("Watched folder '{}' is not available; waiting for it to come back", folder.display()));
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        if !sleep_unless_stopped(folder_retry_delay(attempt), stop).await {
+            return false;
+        }
+        if folder.is_dir() {
+            log_info(&format!("Watched folder '{}' is available again", folder.display()));
+            return true;
+        }
+    }
+}
 
-    let (tx, rx) = std::sync::mpsc::channel();
-    let mut debouncer = match new_debouncer(Duration::from_secs(5), None, tx) {
-        Ok(d) => d,
-        Err(e) => {
-            log_error(&format!("Failed to create file watcher debouncer: {}", e));
-            return;
+// 10s, doubling up to 15 minutes between startup attempts
+#[cfg(windows)]
+fn supervisor_delay(failures: u32) -> Duration {
+    Duration::from_secs((10u64 << failures.saturating_sub(1).min(16)).min(900))
+}
+
+// 10s, then doubled per attempt up to 5 minutes
+fn folder_retry_delay(attempt: u32) -> Duration {
+    let factor = 1u64 << attempt.saturating_sub(1).min(16);
+    Duration::from_secs((FOLDER_CHECK_SECS * factor).min(FOLDER_RETRY_MAX_SECS))
+}
+
+// Returns false if `stop` was set before the delay ran out
+async fn sleep_unless_stopped(delay: Duration, stop: Option<&AtomicBool>) -> bool {
+    let deadline = Instant::now() + delay;
+    while Instant::now() < deadline {
+        if stop.is_some_and(|s| s.load(Ordering::SeqCst)) {
+            return false;
         }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    !stop.is_some_and(|s| s.load(Ordering::SeqCst))
+}
+
+// Uploads files as they are created, with periodic rescans to catch anything the
+// watcher missed. Runs until the folder disappears, the watcher fails or, when
+// given, `stop` is set.
+async fn watch_folder(ctx: &mut SyncContext, stop: Option<&AtomicBool>) -> Result<WatchEnd, String> {
+    let folder = ctx.config.local_folder_path.clone();
+    log_info(&format!("Now watching for new files in: {}", &folder));
+    log_info(&format!("Uploading to: {}", ctx.backend.describe()));
+
+    let (mut watcher, mut rx) = start_watcher(Path::new(&folder), &ctx.config.watch)?;
+
+    // A dry run never records anything, so rescanning would only plan the same
+    // files again
+    let rescan_interval = match ctx.config.watch.rescan_interval_secs {
+        0 => None,
+        _ if ctx.dry_run.is_some() => None,
+        secs => Some(Duration::from_secs(secs)),
     };
+    let mut last_scan = Instant::now();
+    let mut last_folder_check = Instant::now();
 
-    if let Err(e) = debouncer.watcher()
-        .watch(Path::new(&config.local_folder_path), RecursiveMode::NonRecursive)
-    {
-        log_error(&format!("Failed to watch folder '{}': {}", &config.local_folder_path, e));
-        return;
-    }
+    loop {
+        if stop.is_some_and(|s| s.load(Ordering::SeqCst)) {
+            watcher.stop();
+            flush_name_manifest(ctx).await;
+            return Ok(WatchEnd::Stopped);
+        }
+        // Watches on a vanished folder tend to go quiet rather than fail
+        if last_folder_check.elapsed() >= Duration::from_secs(FOLDER_CHECK_SECS) {
+            if !Path::new(&folder).is_dir() {
+                log_error(&format!("Watched folder '{}' is no longer available", &folder));
+                watcher.stop();
+                return Ok(WatchEnd::FolderLost);
+            }
+            last_folder_check = Instant::now();
+        }
+        let manifest_due = ctx
+            .encryption
+            .as_ref()
+            .and_then(|enc| enc.manifest.as_ref())
+            .and_then(|manifest| manifest.dirty_since)
+            .is_some_and(|since| since.elapsed() >= Duration::from_secs(MANIFEST_FLUSH_SECS));
+        if manifest_due {
+            flush_name_manifest(ctx).await;
+        }
+        if rescan_interval.is_some_and(|interval| last_scan.elapsed() >= interval) {
+            let summary = reconcile(ctx, ScanKind::Periodic).await;
+            log_info(&summary.summary());
+            last_scan = Instant::now();
+        }
 
-    for res in rx {
+        let res = match rx.recv_timeout(Duration::from_millis(500)) {
+            Ok(res) => res,
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                return Err(format!("File watcher for '{}' stopped unexpectedly", &folder));
+            }
+        };
         match res {
             Ok(events) => {
                 for event in events {
@@ -121,59 +591,1074 @@ pub async fn run_sync_loop() {
                         for path in &event.paths {
                             log_info(&format!("New file detected: {:?}", &path));
                             tokio::time::sleep(Duration::from_secs(2)).await; // Wait for write to finish
-                            upload_file(&hub, path, &config.gdrive_folder_id).await;
+                            // A rescan may already have picked the file up
+                            if ctx.state.is_up_to_date(&relative_path(&ctx.config, path), path) {
+                                continue;
+                            }
+                            upload_file(ctx, path).await;
                         }
                     }
                 }
             },
-            Err(e) => log_error(&format!("File watch error: {:?}", e)),
+            Err(errors) => {
+                // Errors usually mean dropped events (e.g. buffer overflow), so
+                // compare the whole folder straight away
+                log_error(&format!("File watch error: {:?}", errors));
+                if !Path::new(&folder).is_dir() {
+                    log_error(&format!("Watched folder '{}' is no longer available", &folder));
+                    watcher.stop();
+                    return Ok(WatchEnd::FolderLost);
+                }
+
+                // Running out of inotify watches is permanent for the native backend
+                let watch_limit = errors.iter().any(|e| matches!(e.kind, notify::ErrorKind::MaxFilesWatch));
+                if watch_limit && watcher.is_native() && ctx.config.watch.backend == WatchBackend::Auto {
+                    log_error("Native watch limit reached; switching to polling");
+                    let (poll_watcher, poll_rx) = start_poll_watcher(Path::new(&folder), &ctx.config.watch)?;
+                    std::mem::replace(&mut watcher, poll_watcher).stop();
+                    rx = poll_rx;
+                }
+                if ctx.dry_run.is_none() {
+                    let summary = reconcile(ctx, ScanKind::AfterWatchError).await;
+                    log_info(&summary.summary());
+                    last_scan = Instant::now();
+                }
+            }
         }
     }
 }
 
-async fn upload_file(hub: &DriveHub<HttpsConnector<HttpConnector>>, file_path: &PathBuf, parent_folder_id: &str) {
-    if !file_path.is_file() {
-        return;
+// The active debouncer; the watch ends when it is stopped or dropped
+enum FolderWatcher {
+    Native(Debouncer<RecommendedWatcher, FileIdMap>),
+    Poll(Debouncer<PollWatcher, FileIdMap>),
+}
+
+impl FolderWatcher {
+    fn is_native(&self) -> bool {
+        matches!(self, FolderWatcher::Native(_))
+    }
+
+    fn stop(self) {
+        match self {
+            FolderWatcher::Native(debouncer) => debouncer.stop(),
+            FolderWatcher::Poll(debouncer) => debouncer.stop(),
+        }
+    }
+}
+
+type WatchEvents = std::sync::mpsc::Receiver<DebounceEventResult>;
+
+fn start_watcher(folder: &Path, settings: &WatchConfig) -> Result<(FolderWatcher, WatchEvents), String> {
+    match settings.backend {
+        WatchBackend::Native => start_native_watcher(folder),
+        WatchBackend::Poll => start_poll_watcher(folder, settings),
+        WatchBackend::Auto => start_native_watcher(folder).or_else(|e| {
+            log_error(&format!("{}; falling back to polling", e));
+            start_poll_watcher(folder, settings)
+        }),
+    }
+}
+
+fn start_native_watcher(folder: &Path) -> Result<(FolderWatcher, WatchEvents), String> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut debouncer = new_debouncer(Duration::from_secs(5), None, tx)
+        .map_err(|e| format!("Failed to create file watcher debouncer: {}", e))?;
+
+    debouncer.watcher()
+        .watch(folder, RecursiveMode::NonRecursive)
+        .map_err(|e| format!("Failed to watch folder '{}': {}", folder.display(), e))?;
+
+    log_info("Using native change notifications");
+    Ok((FolderWatcher::Native(debouncer), rx))
+}
+
+// Lists the folder every poll_interval_secs and reports differences as events,
+// which also works where no change notifications arrive (SMB and NFS mounts)
+fn start_poll_watcher(folder: &Path, settings: &WatchConfig) -> Result<(FolderWatcher, WatchEvents), String> {
+    let interval = Duration::from_secs(settings.poll_interval_secs.max(1));
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut debouncer = new_debouncer_opt::<_, PollWatcher, FileIdMap>(
+        Duration::from_secs(5),
+        None,
+        tx,
+        FileIdMap::new(),
+        notify::Config::default().with_poll_interval(interval),
+    )
+    .map_err(|e| format!("Failed to create polling file watcher: {}", e))?;
+
+    debouncer.watcher()
+        .watch(folder, RecursiveMode::NonRecursive)
+        .map_err(|e| format!("Failed to watch folder '{}': {}", folder.display(), e))?;
+
+    log_info(&format!("Polling the folder for changes every {}s", interval.as_secs()));
+    Ok((FolderWatcher::Poll(debouncer), rx))
+}
+
+// Path relative to the watched folder, the key used by the sync state and manifest
+fn relative_path(config: &Config, file_path: &Path) -> String {
+    file_path
+        .strip_prefix(&config.local_folder_path)
+        .unwrap_or(file_path)
+        .to_string_lossy()
+        .to_string()
+}
+
+async fn upload_file(ctx: &mut SyncContext, file_path: &Path) -> UploadOutcome {
+    let config = &ctx.config;
+    match local_files::classify(file_path, Path::new(&config.local_folder_path), config.symlinks) {
+        EntryKind::File => {}
+        EntryKind::Skipped(reason) => {
+            log_info(&format!("Skipping {:?}: {}", file_path, reason));
+            return UploadOutcome::Ignored;
+        }
+        EntryKind::Directory | EntryKind::Missing => return UploadOutcome::Ignored,
     }
     
     let file_name = match file_path.file_name().and_then(|n| n.to_str()) {
         Some(name) => name,
         None => {
             log_error(&format!("Failed to get file name from path: {:?}", file_path));
-            return;
+            return UploadOutcome::Failed;
+        }
+    };
+
+    let relative_path = relative_path(config, file_path);
+    let remote_name = match &ctx.encryption {
+        Some(EncryptionContext { manifest: Some(manifest), .. }) => manifest.remote_name(&relative_path),
+        Some(_) => format!("{}.{}", file_name, encryption::ENCRYPTED_EXTENSION),
+        None => file_name.to_string(),
+    };
+
+    // Taken before hashing, so after_upload can tell whether the file changed since
+    let snapshot = match fs::metadata(file_path) {
+        Ok(m) => post_upload::Snapshot::of(&m),
+        Err(e) => {
+            log_error(&format!("Failed to read metadata of {:?}: {}", file_path, e));
+            return UploadOutcome::Failed;
         }
     };
+    let local_size = snapshot.size;
 
-    let mut remote_file = api::File::default();
-    remote_file.name = Some(file_name.to_string());
-    remote_file.parents = Some(vec![parent_folder_id.to_string()]);
+    if let Some(plan) = ctx.dry_run.as_mut() {
+        plan_upload(ctx.backend.as_ref(), config, plan, file_path, &remote_name, local_size).await;
+        return UploadOutcome::Planned;
+    }
 
-    let file_content = match fs::File::open(file_path) {
-        Ok(f) => f,
+    // With encryption on, the ciphertext is staged locally and uploaded under a
+    // marker extension; the staged copy is removed when `encrypted` is dropped
+    let encrypted = match &ctx.encryption {
+        Some(enc) => match encryption::encrypt_to_staging(file_path, &enc.key) {
+            Ok(staged) => Some(staged),
+            Err(e) => {
+                log_error(&format!("Failed to encrypt '{}': {}", file_name, e));
+                return UploadOutcome::Failed;
+            }
+        },
+        None => None,
+    };
+    let upload_path = match &encrypted {
+        Some(staged) => staged.path.as_path(),
+        None => file_path,
+    };
+
+    // Bytes actually sent, which is the ciphertext size when encrypting
+    let file_size = match fs::metadata(upload_path) {
+        Ok(m) => m.len(),
+        Err(e) => {
+            log_error(&format!("Failed to read metadata of {:?}: {}", upload_path, e));
+            return UploadOutcome::Failed;
+        }
+    };
+
+    // Hash the local (plaintext) content so the audit record and the uploaded file's
+    // provenance properties identify the file itself
+    let sha256 = match checksum::sha256_file(file_path) {
+        Ok(hash) => hash,
         Err(e) => {
-            log_error(&format!("Failed to open file {:?}: {}", file_path, e));
+            log_error(&format!("Failed to hash file {:?}: {}", file_path, e));
+            String::new()
+        }
+    };
+
+    // Compared with the checksum the destination reports for the uploaded content
+    let local_md5 = match checksum::md5_file(upload_path) {
+        Ok(hash) => hash,
+        Err(e) => {
+            log_error(&format!("Failed to hash file {:?}: {}", upload_path, e));
+            return UploadOutcome::Failed;
+        }
+    };
+
+    let local_path = file_path.display().to_string();
+    let mut audit_entry = AuditEntry::new(&local_path, &ctx.backend.describe(), "failed");
+    audit_entry.size = local_size;
+    audit_entry.sha256 = sha256.clone();
+
+    // Apply the configured policy when the name is already taken
+    let resolution = resolve_name_conflict(ctx.backend.as_ref(), config, &remote_name).await;
+    let (remote_name, overwrite_id) = match resolution {
+        Ok(NameResolution::Create(name)) => (name, None),
+        Ok(NameResolution::Overwrite(existing_id)) => (remote_name, Some(existing_id)),
+        Ok(NameResolution::Skip(existing_id)) => {
+            log_info(&format!(
+                "'{}' already exists at the destination (ID: {}); skipping as configured",
+                remote_name, existing_id
+            ));
+            let record = SyncRecord {
+                size: local_size,
+                modified: sync_state::modified_secs(file_path),
+                md5: local_md5,
+                drive_file_id: existing_id.clone(),
+                remote_name,
+                verified: false,
+                uploaded_at: chrono::Utc::now().to_rfc3339(),
+            };
+            if let Err(e) = ctx.state.insert(&relative_path, record) {
+                log_error(&e);
+            }
+            audit_entry.outcome = "skipped".to_string();
+            audit_entry.drive_file_id = existing_id;
+            audit_log::record(&audit_entry);
+            return UploadOutcome::Skipped;
+        }
+        Err(e) => {
+            log_error(&format!("Failed to check for name conflicts for '{}': {}", file_name, e));
+            audit_entry.error = e;
+            audit_log::record(&audit_entry);
+            notify(upload_notification(WebhookEvent::UploadFailed, &audit_entry, &remote_name, 0));
+            return UploadOutcome::Failed;
+        }
+    };
+
+    let metadata = upload_metadata(file_path, &remote_name, &relative_path, &sha256, config);
+
+    match &overwrite_id {
+        Some(id) => log_info(&format!("Uploading '{}' to {} as a new revision of {}", file_name, ctx.backend.describe(), id)),
+        None => log_info(&format!("Uploading '{}' to {} as '{}'", file_name, ctx.backend.describe(), remote_name)),
+    }
+
+    let max_attempts = config.upload.max_attempts.max(1);
+    let mut last_error = String::new();
+    let mut verified_id = None;
+    let mut attempts = 0;
+    for attempt in 1..=max_attempts {
+        attempts = attempt;
+        if attempt > 1 {
+            let delay = retry_delay(config, attempt);
+            log_info(&format!(
+                "Retrying upload of '{}' in {}s (attempt {}/{})",
+                file_name, delay.as_secs(), attempt, max_attempts
+            ));
+            tokio::time::sleep(delay).await;
+        }
+
+        let started = Instant::now();
+        let result = match &overwrite_id {
+            Some(id) => ctx.backend.update(id, upload_path, &metadata).await,
+            None => ctx.backend.create(ctx.backend.root_folder(), upload_path, &metadata).await,
+        };
+        let duration_ms = started.elapsed().as_millis() as u64;
+
+        match result {
+            Ok(file) => {
+                let file_id = file.id;
+                // Destinations that report no checksum are verified by size alone
+                let md5_matches = file.md5.as_deref().is_none_or(|md5| md5 == local_md5);
+                if file.size == Some(file_size) && md5_matches {
+                    let checked = if file.md5.is_some() { "size and md5" } else { "size" };
+                    log::info!(
+                        path:% = file_path.display(),
+                        drive_file_id = file_id.as_str(),
+                        bytes = file_size,
+                        duration_ms = duration_ms,
+                        mapping = config.local_folder_path.as_str();
+                        "Successfully uploaded '{}' with ID: {} ({} verified)", file_name, file_id, checked
+                    );
+                    verified_id = Some(file_id);
+                    break;
+                }
+
+                last_error = format!(
+                    "verification failed: destination reported size {:?} and md5 {:?}, expected {} and {}",
+                    file.size, file.md5, file_size, local_md5
+                );
+                log::error!(
+                    path:% = file_path.display(),
+                    drive_file_id = file_id.as_str(),
+                    bytes = file_size,
+                    duration_ms = duration_ms,
+                    mapping = config.local_folder_path.as_str();
+                    "Upload of '{}' did not verify ({})", file_name, last_error
+                );
+                // A bad new revision is simply replaced by the retry; trashing an
+                // overwritten file would take its earlier revisions with it
+                if overwrite_id.is_none() {
+                    log_info(&format!("Moving unverified copy {} to trash", file_id));
+                    if let Err(e) = ctx.backend.trash(&file_id).await {
+                        log_error(&e);
+                    }
+                }
+            }
+            Err(e) => {
+                log::error!(
+                    path:% = file_path.display(),
+                    bytes = file_size,
+                    duration_ms = duration_ms,
+                    mapping = config.local_folder_path.as_str(),
+                    error:% = e;
+                    "Failed to upload '{}' (attempt {}/{}). Error: {}", file_name, attempt, max_attempts, e
+                );
+                last_error = e;
+            }
+        }
+    }
+
+    // Verification outcome is kept with the sync record; an unverified record
+    // tells later passes that the file still needs uploading
+    let record = SyncRecord {
+        size: local_size,
+        modified: sync_state::modified_secs(file_path),
+        md5: local_md5,
+        drive_file_id: verified_id.clone().unwrap_or_default(),
+        remote_name: remote_name.clone(),
+        verified: verified_id.is_some(),
+        uploaded_at: chrono::Utc::now().to_rfc3339(),
+    };
+    if let Err(e) = ctx.state.insert(&relative_path, record) {
+        log_error(&e);
+    }
+
+    let file_id = match verified_id {
+        Some(id) => id,
+        None => {
+            log_error(&format!(
+                "Giving up on '{}' after {} attempt(s): {}",
+                file_name, max_attempts, last_error
+            ));
+            audit_entry.error = last_error;
+            audit_log::record(&audit_entry);
+            notify(upload_notification(WebhookEvent::UploadFailed, &audit_entry, &remote_name, attempts));
+            return UploadOutcome::Failed;
+        }
+    };
+
+    // Uploaded with the rest of the pass, or shortly after in watch mode
+    if let Some(manifest) = ctx.encryption.as_mut().and_then(|enc| enc.manifest.as_mut()) {
+        manifest.record(&remote_name, &relative_path);
+    }
+
+    audit_entry.outcome = if overwrite_id.is_some() { "overwritten" } else { "uploaded" }.to_string();
+    audit_entry.drive_file_id = file_id;
+    audit_log::record(&audit_entry);
+    notify(upload_notification(WebhookEvent::UploadSucceeded, &audit_entry, &remote_name, attempts));
+
+    // Only reached with a verified remote copy, so the local file may be removed
+    if let Err(e) = post_upload::apply(file_path, &snapshot, &config.after_upload) {
+        log_error(&e);
+    }
+
+    check_quota(ctx).await;
+    UploadOutcome::Uploaded
+}
+
+// Hands an event to every configured notification channel
+fn notify(notification: Notification) {
+    alerts::record(&notification);
+    webhooks::send(notification);
+}
+
+fn upload_notification(event: WebhookEvent, entry: &AuditEntry, remote_name: &str, attempts: u32) -> Notification {
+    let message = match event {
+        WebhookEvent::UploadSucceeded => format!("Uploaded {} as '{}'", entry.local_path, remote_name),
+        _ => format!("Failed to upload {}: {}", entry.local_path, entry.error),
+    };
+    let mut notification = Notification::new(event, &message);
+    notification.file = Some(FileDetails {
+        local_path: entry.local_path.clone(),
+        remote_name: remote_name.to_string(),
+        size: entry.size,
+        drive_file_id: entry.drive_file_id.clone(),
+        destination_folder: entry.destination_folder.clone(),
+        attempts,
+        error: entry.error.clone(),
+    });
+    notification
+}
+
+// Raises a quota warning when destination storage use reaches the configured share of
+// the limit. Checked after uploads, at most every check_interval_mins.
+async fn check_quota(ctx: &mut SyncContext) {
+    let settings = &ctx.config.quota;
+    if settings.warning_percent == 0 {
+        return;
+    }
+    let interval = Duration::from_secs(settings.check_interval_mins * 60);
+    if ctx.last_quota_check.is_some_and(|checked| checked.elapsed() < interval) {
+        return;
+    }
+    ctx.last_quota_check = Some(Instant::now());
+
+    let (usage, limit) = match ctx.backend.quota().await {
+        Ok(Some(quota)) => (quota.usage, quota.limit),
+        Ok(None) => return,
+        Err(e) => {
+            log_error(&e);
             return;
         }
     };
 
-    log_info(&format!("Uploading '{}' to Google Drive", file_name));
+    let percent = usage as f64 * 100.0 / limit as f64;
+    let over = percent >= f64::from(settings.warning_percent);
+    // Warn once when the level is crossed rather than after every upload
+    if over && !ctx.quota_warned {
+        let message = format!("Destination storage is {:.1}% full ({} of {} bytes used)", percent, usage, limit);
+        log_error(&message);
+        let mut notification = Notification::new(WebhookEvent::QuotaWarning, &message);
+        notification.quota = Some(QuotaDetails { usage_bytes: usage, limit_bytes: limit, percent_used: percent });
+        notify(notification);
+    }
+    ctx.quota_warned = over;
+}
+
+// Dry run counterpart of the upload: the same naming and conflict checks, with
+// the outcome logged and tallied instead of carried out
+async fn plan_upload(
+    backend: &dyn StorageBackend,
+    config: &Config,
+    plan: &mut DryRunPlan,
+    file_path: &Path,
+    remote_name: &str,
+    local_size: u64,
+) {
+    let bytes = if config.encryption.enabled { encryption::encrypted_size(local_size) } else { local_size };
 
-    let mime_type = match "application/octet-stream".parse() {
-        Ok(mt) => mt,
+    match resolve_name_conflict(backend, config, remote_name).await {
+        Ok(NameResolution::Create(name)) => {
+            log::info!(path:% = file_path.display(), bytes = bytes; "[dry run] Would create '{}' ({} bytes)", name, bytes);
+            plan.creates += 1;
+        }
+        Ok(NameResolution::Overwrite(existing_id)) => {
+            log::info!(
+                path:% = file_path.display(), bytes = bytes, drive_file_id = existing_id.as_str();
+                "[dry run] Would update '{}' (ID: {}) with a new revision ({} bytes)", remote_name, existing_id, bytes
+            );
+            plan.updates += 1;
+        }
+        Ok(NameResolution::Skip(existing_id)) => {
+            log_info(&format!(
+                "[dry run] Would skip {:?}: '{}' already exists at the destination (ID: {})",
+                file_path, remote_name, existing_id
+            ));
+            plan.skips += 1;
+            return;
+        }
         Err(e) => {
-            log_error(&format!("Failed to parse MIME type: {}", e));
+            log_error(&format!("[dry run] Failed to check for name conflicts for {:?}: {}", file_path, e));
+            plan.errors += 1;
             return;
         }
+    }
+    plan.bytes += bytes;
+
+    match config.after_upload.action {
+        AfterUploadAction::Keep => {}
+        AfterUploadAction::Delete => {
+            log_info(&format!("[dry run] Would delete {:?} after upload", file_path));
+            plan.local_deletes += 1;
+        }
+        AfterUploadAction::Move => {
+            log_info(&format!("[dry run] Would move {:?} to the archive after upload", file_path));
+            plan.local_moves += 1;
+        }
+    }
+}
+
+// Exponential backoff: retry_delay_secs, then doubled per attempt, capped at 5 minutes
+fn retry_delay(config: &Config, attempt: u32) -> Duration {
+    let base = config.upload.retry_delay_secs.max(1);
+    let factor = 1u64 << (attempt.saturating_sub(2)).min(16);
+    Duration::from_secs((base * factor).min(300))
+}
+
+// Drive limits each appProperties key + value to 124 bytes
+const APP_PROPERTY_MAX_BYTES: usize = 124;
+
+// Metadata for the uploaded file: local timestamps, so the destination sorts and
+// restores by when the file was written rather than when it was uploaded, plus
+// properties tracing it back to the machine and path it came from
+fn upload_metadata(
+    file_path: &Path,
+    remote_name: &str,
+    relative_path: &str,
+    content_sha256: &str,
+    config: &Config,
+) -> UploadMetadata {
+    let mut remote_file = UploadMetadata {
+        name: remote_name.to_string(),
+        ..UploadMetadata::default()
+    };
+
+    let metadata = fs::metadata(file_path).ok();
+    remote_file.modified_time = metadata
+        .as_ref()
+        .and_then(|m| m.modified().ok())
+        .map(chrono::DateTime::<chrono::Utc>::from);
+    remote_file.created_time = metadata
+        .as_ref()
+        .and_then(|m| m.created().ok())
+        .map(chrono::DateTime::<chrono::Utc>::from);
+
+    // Encrypted uploads must not leak what encryption hides: the plaintext hash is
+    // never attached, and the path only when names are not encrypted
+    let encrypting = config.encryption.enabled;
+    let mut properties = std::collections::HashMap::new();
+    properties.insert("source_host".to_string(), source_hostname());
+    if !(encrypting && config.encryption.encrypt_names) {
+        properties.insert("source_path".to_string(), sync_state::normalize(relative_path));
+    }
+    if let Some(modified) = remote_file.modified_time {
+        properties.insert("source_mtime".to_string(), modified.to_rfc3339());
+    }
+    if !encrypting && !content_sha256.is_empty() {
+        properties.insert("content_sha256".to_string(), content_sha256.to_string());
+    }
+    for (key, value) in properties.iter_mut() {
+        truncate_app_property(key, value);
+    }
+    remote_file.properties = properties;
+
+    remote_file
+}
+
+pub fn source_hostname() -> String {
+    hostname::get()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+// Keeps the end of long values (the file name end of a path is the useful part)
+fn truncate_app_property(key: &str, value: &mut String) {
+    let budget = APP_PROPERTY_MAX_BYTES.saturating_sub(key.len());
+    if value.len() <= budget {
+        return;
+    }
+    if budget <= 3 {
+        value.clear();
+        return;
+    }
+    let mut start = value.len() + 3 - budget;
+    while !value.is_char_boundary(start) {
+        start += 1;
+    }
+    *value = format!("...{}", &value[start..]);
+}
+
+enum NameResolution {
+    // Create a new file under this name
+    Create(String),
+    // Upload as a new revision of the existing file with this ID
+    Overwrite(String),
+    // Leave the existing file with this ID alone
+    Skip(String),
+}
+
+async fn resolve_name_conflict(backend: &dyn StorageBackend, config: &Config, remote_name: &str) -> Result<NameResolution, String> {
+    let mut policy = config.upload.on_name_conflict;
+    if policy == NameConflictPolicy::Duplicate {
+        if backend.allows_duplicate_names() {
+            return Ok(NameResolution::Create(remote_name.to_string()));
+        }
+        // Two files cannot share a name here, so keep both under different names
+        policy = NameConflictPolicy::Rename;
+    }
+
+    let folder_id = backend.root_folder();
+    let existing_id = match find_file_in_folder(backend, folder_id, remote_name).await? {
+        Some(id) => id,
+        None => return Ok(NameResolution::Create(remote_name.to_string())),
     };
 
-    let result = hub
-        .files()
-        .create(remote_file)
-        .upload(file_content, mime_type)
-        .await;
+    match policy {
+        NameConflictPolicy::Duplicate => Ok(NameResolution::Create(remote_name.to_string())),
+        NameConflictPolicy::Skip => Ok(NameResolution::Skip(existing_id)),
+        NameConflictPolicy::Overwrite => Ok(NameResolution::Overwrite(existing_id)),
+        NameConflictPolicy::Rename => {
+            // One listing of the folder instead of a lookup per candidate name
+            let taken: std::collections::HashSet<String> =
+                backend.list(folder_id, None).await?.into_iter().map(|file| file.name).collect();
+            if config.upload.rename_suffix == RenameSuffix::Timestamp {
+                let suffix = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+                let candidate = suffixed_name(remote_name, &format!("_{}", suffix));
+                if !taken.contains(&candidate) {
+                    return Ok(NameResolution::Create(candidate));
+                }
+            }
+            // Numbered suffixes, also the fallback when the timestamped name is taken
+            (1..=999)
+                .map(|n| suffixed_name(remote_name, &format!(" ({})", n)))
+                .find(|candidate| !taken.contains(candidate))
+                .map(NameResolution::Create)
+                .ok_or_else(|| format!("No free name found for '{}'", remote_name))
+        }
+    }
+}
+
+// Inserts the suffix before the extension: "report.pdf" -> "report (1).pdf"
+fn suffixed_name(name: &str, suffix: &str) -> String {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => format!("{}{}{}", &name[..dot], suffix, &name[dot..]),
+        _ => format!("{}{}", name, suffix),
+    }
+}
+
+// Loads the local name manifest and merges in the copy already stored at the destination,
+// so a fresh install never overwrites names recorded by an earlier one. Uploads
+// recorded in the sync state but not yet in the manifest (the service stopped
+// before it was saved) are added back.
+async fn open_name_manifest(
+    backend: &dyn StorageBackend,
+    base_dir: &Path,
+    key: &KeyMaterial,
+    state: &SyncState,
+) -> Result<NameManifest, String> {
+    let mut manifest = NameManifest::open(base_dir.join("name_manifest.enc"), key)?;
+    let mut restored = 0;
+    for (relative_path, record) in state.uploaded() {
+        // Only names this key derived, possibly with a rename suffix
+        let derived = manifest.remote_name(relative_path);
+        let stem = derived.trim_end_matches(&format!(".{}", encryption::ENCRYPTED_EXTENSION));
+        if record.remote_name.starts_with(stem) && manifest.record(&record.remote_name, relative_path) {
+            restored += 1;
+        }
+    }
+    if restored > 0 {
+        log_info(&format!("Restored {} name manifest entries from the sync state", restored));
+    }
+
+    manifest.remote_id = find_file_in_folder(backend, backend.root_folder(), name_manifest::MANIFEST_NAME).await?;
+    if let Some(remote_id) = manifest.remote_id.clone() {
+        let download_path = base_dir.join("name_manifest.remote.enc");
+        backend.download(&remote_id, &download_path).await?;
+        let merged = manifest.merge_from(&download_path, key);
+        let _ = fs::remove_file(&download_path);
+        let added = merged?;
+        if added > 0 {
+            manifest.save(key)?;
+            log_info(&format!("Restored {} name manifest entries from the destination", added));
+        }
+    } else if !manifest.is_empty() {
+        manifest.dirty_since.get_or_insert_with(Instant::now);
+    }
+
+    log_info("Remote file names are encrypted; real names are kept in the encrypted name manifest");
+    Ok(manifest)
+}
+
+// Saves the manifest locally and replaces the copy at the destination
+async fn upload_name_manifest(backend: &dyn StorageBackend, manifest: &mut NameManifest, key: &KeyMaterial) -> Result<(), String> {
+    manifest.save(key)?;
+
+    let metadata = UploadMetadata {
+        name: name_manifest::MANIFEST_NAME.to_string(),
+        ..UploadMetadata::default()
+    };
+    let result = match &manifest.remote_id {
+        Some(id) => backend.update(id, manifest.local_path(), &metadata).await,
+        None => backend.create(backend.root_folder(), manifest.local_path(), &metadata).await,
+    };
 
     match result {
-        Ok((_, file)) => log_info(&format!("Successfully uploaded '{}' with ID: {}", file_name, file.id.unwrap_or_default())),
-        Err(e) => log_error(&format!("Failed to upload '{}'. Error: {}", file_name, e)),
+        Ok(file) => {
+            if manifest.remote_id.is_none() {
+                manifest.remote_id = Some(file.id);
+            }
+            Ok(())
+        }
+        Err(e) => Err(format!("Failed to upload name manifest: {}", e)),
+    }
+}
+
+// Saves and uploads the name manifest if files were recorded since the last
+// upload, retrying like a file upload. On failure it stays pending and is tried
+// again after the next pass or MANIFEST_FLUSH_SECS.
+async fn flush_name_manifest(ctx: &mut SyncContext) {
+    if ctx.dry_run.is_some() {
+        return;
+    }
+    let (key, manifest) = match ctx.encryption.as_mut() {
+        Some(EncryptionContext { key, manifest: Some(manifest) }) if manifest.dirty_since.is_some() => (key, manifest),
+        _ => return,
+    };
+    let max_attempts = ctx.config.upload.max_attempts.max(1);
+    for attempt in 1..=max_attempts {
+        if attempt > 1 {
+            tokio::time::sleep(retry_delay(&ctx.config, attempt)).await;
+        }
+        match upload_name_manifest(ctx.backend.as_ref(), manifest, key).await {
+            Ok(()) => {
+                manifest.dirty_since = None;
+                return;
+            }
+            Err(e) => log_error(&format!("{} (attempt {}/{})", e, attempt, max_attempts)),
+        }
+    }
+    manifest.dirty_since = Some(Instant::now());
+}
+
+// Returns the ID of a file with this exact name directly in the folder
+async fn find_file_in_folder(backend: &dyn StorageBackend, folder_id: &str, name: &str) -> Result<Option<String>, String> {
+    let files = backend.list(folder_id, Some(name)).await?;
+    Ok(files.into_iter().next().map(|f| f.id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{LocalBackend, RemoteFile, StorageQuota};
+    use crate::test_support;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    // A watched folder and a local destination under a fresh temp directory;
+    // `settings` are merged over a config with a single upload attempt
+    fn context(name: &str, settings: serde_json::Value) -> (SyncContext, PathBuf) {
+        let root = test_support::temp_dir(name);
+        fs::create_dir_all(root.join("watched")).unwrap();
+
+        let mut config = serde_json::json!({
+            "local_folder_path": root.join("watched"),
+            "destination": { "type": "local", "path": root.join("dest") },
+            "upload": { "max_attempts": 1 },
+        });
+        for (key, value) in settings.as_object().unwrap() {
+            config[key] = value.clone();
+        }
+        let config: Config = serde_json::from_value(config).unwrap();
+
+        let ctx = SyncContext {
+            backend: Box::new(LocalBackend::open(root.join("dest").to_str().unwrap()).unwrap()),
+            config,
+            encryption: None,
+            state: SyncState::load(root.join("sync_state.json")).unwrap(),
+            dry_run: None,
+            last_quota_check: None,
+            quota_warned: false,
+        };
+        (ctx, root)
+    }
+
+    fn write_local(root: &Path, name: &str, content: &str) -> PathBuf {
+        let path = root.join("watched").join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn read_dest(root: &Path, name: &str) -> String {
+        fs::read_to_string(root.join("dest").join(name)).unwrap()
+    }
+
+    // Stores files like LocalBackend but reports one byte more than it stored,
+    // so every upload fails verification
+    struct Misreporting(LocalBackend);
+
+    #[async_trait::async_trait]
+    impl StorageBackend for Misreporting {
+        fn describe(&self) -> String {
+            self.0.describe()
+        }
+        fn root_folder(&self) -> &str {
+            self.0.root_folder()
+        }
+        async fn create(&self, folder_id: &str, source: &Path, metadata: &UploadMetadata) -> Result<RemoteFile, String> {
+            let mut file = self.0.create(folder_id, source, metadata).await?;
+            file.size = file.size.map(|size| size + 1);
+            Ok(file)
+        }
+        async fn update(&self, file_id: &str, source: &Path, metadata: &UploadMetadata) -> Result<RemoteFile, String> {
+            let mut file = self.0.update(file_id, source, metadata).await?;
+            file.size = file.size.map(|size| size + 1);
+            Ok(file)
+        }
+        async fn list(&self, folder_id: &str, name: Option<&str>) -> Result<Vec<RemoteFile>, String> {
+            self.0.list(folder_id, name).await
+        }
+        async fn mkdir(&self, parent_id: &str, name: &str) -> Result<String, String> {
+            self.0.mkdir(parent_id, name).await
+        }
+        async fn trash(&self, file_id: &str) -> Result<(), String> {
+            self.0.trash(file_id).await
+        }
+        async fn metadata(&self, file_id: &str) -> Result<RemoteFile, String> {
+            self.0.metadata(file_id).await
+        }
+        async fn download(&self, file_id: &str, destination: &Path) -> Result<(), String> {
+            self.0.download(file_id, destination).await
+        }
+        async fn quota(&self) -> Result<Option<StorageQuota>, String> {
+            self.0.quota().await
+        }
+    }
+
+    #[tokio::test]
+    async fn uploads_a_new_file_and_records_it() {
+        let (mut ctx, root) = context("engine-create", serde_json::json!({}));
+        let path = write_local(&root, "report.txt", "quarterly numbers");
+
+        assert!(matches!(upload_file(&mut ctx, &path).await, UploadOutcome::Uploaded));
+
+        assert_eq!(read_dest(&root, "report.txt"), "quarterly numbers");
+        assert!(ctx.state.is_up_to_date("report.txt", &path));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn name_conflict_policies() {
+        let cases = [
+            ("skip", "old", None),
+            ("overwrite", "new", None),
+            ("rename", "old", Some("report (2).txt")),
+            // The local destination cannot hold two files with one name
+            ("duplicate", "old", Some("report (2).txt")),
+        ];
+        for (policy, expected, renamed) in cases {
+            let (mut ctx, root) = context(
+                &format!("engine-conflict-{}", policy),
+                serde_json::json!({ "upload": { "max_attempts": 1, "on_name_conflict": policy } }),
+            );
+            fs::create_dir_all(root.join("dest")).unwrap();
+            fs::write(root.join("dest").join("report.txt"), "old").unwrap();
+            fs::write(root.join("dest").join("report (1).txt"), "older").unwrap();
+            let path = write_local(&root, "report.txt", "new");
+
+            let outcome = upload_file(&mut ctx, &path).await;
+
+            match policy {
+                "skip" => assert!(matches!(outcome, UploadOutcome::Skipped), "{}", policy),
+                _ => assert!(matches!(outcome, UploadOutcome::Uploaded), "{}", policy),
+            }
+            assert_eq!(read_dest(&root, "report.txt"), expected, "{}", policy);
+            assert_eq!(read_dest(&root, "report (1).txt"), "older", "{}", policy);
+            match renamed {
+                Some(name) => assert_eq!(read_dest(&root, name), "new", "{}", policy),
+                None => assert!(!root.join("dest").join("report (2).txt").exists(), "{}", policy),
+            }
+            fs::remove_dir_all(&root).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn unverified_uploads_are_trashed_retried_and_keep_the_local_file() {
+        let (mut ctx, root) = context(
+            "engine-unverified",
+            serde_json::json!({
+                "upload": { "max_attempts": 2, "retry_delay_secs": 1 },
+                "after_upload": { "action": "delete" },
+            }),
+        );
+        ctx.backend = Box::new(Misreporting(LocalBackend::open(root.join("dest").to_str().unwrap()).unwrap()));
+        let path = write_local(&root, "report.txt", "quarterly numbers");
+
+        assert!(matches!(upload_file(&mut ctx, &path).await, UploadOutcome::Failed));
+
+        // Both attempts were uploaded, failed verification and went to the trash
+        assert!(!root.join("dest").join("report.txt").exists());
+        assert_eq!(fs::read_dir(root.join("dest").join(".trash")).unwrap().count(), 2);
+        assert!(path.exists(), "after_upload must not run without a verified copy");
+        assert!(!ctx.state.is_up_to_date("report.txt", &path));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn after_upload_delete_and_move_run_once_verified() {
+        let (mut ctx, root) = context("engine-after-delete", serde_json::json!({ "after_upload": { "action": "delete" } }));
+        let path = write_local(&root, "report.txt", "quarterly numbers");
+        assert!(matches!(upload_file(&mut ctx, &path).await, UploadOutcome::Uploaded));
+        assert!(!path.exists());
+        assert_eq!(read_dest(&root, "report.txt"), "quarterly numbers");
+        fs::remove_dir_all(&root).unwrap();
+
+        let (mut ctx, root) = context("engine-after-move", serde_json::json!({}));
+        ctx.config.after_upload = serde_json::from_value(serde_json::json!({
+            "action": "move",
+            "archive_dir": root.join("archive"),
+        }))
+        .unwrap();
+        let path = write_local(&root, "report.txt", "quarterly numbers");
+        assert!(matches!(upload_file(&mut ctx, &path).await, UploadOutcome::Uploaded));
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(root.join("archive").join("report.txt")).unwrap(), "quarterly numbers");
+
+        // Without a verified copy the file stays where it is
+        ctx.backend = Box::new(Misreporting(LocalBackend::open(root.join("dest").to_str().unwrap()).unwrap()));
+        let path = write_local(&root, "notes.txt", "draft");
+        assert!(matches!(upload_file(&mut ctx, &path).await, UploadOutcome::Failed));
+        assert!(path.exists());
+        assert!(!root.join("archive").join("notes.txt").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn first_sync_records_copies_already_at_the_destination() {
+        let (mut ctx, root) = context("engine-adopt", serde_json::json!({}));
+        fs::create_dir_all(root.join("dest")).unwrap();
+        fs::write(root.join("dest").join("report.txt"), "quarterly numbers").unwrap();
+        fs::write(root.join("dest").join("notes.txt"), "old draft").unwrap();
+        let report = write_local(&root, "report.txt", "quarterly numbers");
+        let notes = write_local(&root, "notes.txt", "new draft");
+        let scan = write_local(&root, "scan.pdf", "scanned");
+
+        let summary = reconcile(&mut ctx, ScanKind::Initial).await;
+
+        assert_eq!((summary.up_to_date, summary.uploaded, summary.failed), (1, 2, 0));
+        // The identical copy is recorded rather than uploaded again as a duplicate
+        assert!(!root.join("dest").join("report (1).txt").exists());
+        assert_eq!(read_dest(&root, "notes (1).txt"), "new draft");
+        assert_eq!(read_dest(&root, "scan.pdf"), "scanned");
+        for path in [&report, &notes, &scan] {
+            let name = path.file_name().unwrap().to_str().unwrap();
+            assert!(ctx.state.is_up_to_date(name, path), "{}", name);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    // Delegates to LocalBackend, counting manifest uploads and failing the
+    // first `failures` of them
+    struct ManifestCounter {
+        inner: LocalBackend,
+        writes: Arc<AtomicUsize>,
+        failures: Arc<AtomicUsize>,
+    }
+
+    impl ManifestCounter {
+        fn write(&self, name: &str) -> Result<(), String> {
+            if name != name_manifest::MANIFEST_NAME {
+                return Ok(());
+            }
+            self.writes.fetch_add(1, Ordering::SeqCst);
+            match self.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)) {
+                Ok(_) => Err("destination unavailable".to_string()),
+                Err(_) => Ok(()),
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl StorageBackend for ManifestCounter {
+        fn describe(&self) -> String {
+            self.inner.describe()
+        }
+        fn root_folder(&self) -> &str {
+            self.inner.root_folder()
+        }
+        async fn create(&self, folder_id: &str, source: &Path, metadata: &UploadMetadata) -> Result<RemoteFile, String> {
+            self.write(&metadata.name)?;
+            self.inner.create(folder_id, source, metadata).await
+        }
+        async fn update(&self, file_id: &str, source: &Path, metadata: &UploadMetadata) -> Result<RemoteFile, String> {
+            self.write(&metadata.name)?;
+            self.inner.update(file_id, source, metadata).await
+        }
+        async fn list(&self, folder_id: &str, name: Option<&str>) -> Result<Vec<RemoteFile>, String> {
+            self.inner.list(folder_id, name).await
+        }
+        async fn mkdir(&self, parent_id: &str, name: &str) -> Result<String, String> {
+            self.inner.mkdir(parent_id, name).await
+        }
+        async fn trash(&self, file_id: &str) -> Result<(), String> {
+            self.inner.trash(file_id).await
+        }
+        async fn metadata(&self, file_id: &str) -> Result<RemoteFile, String> {
+            self.inner.metadata(file_id).await
+        }
+        async fn download(&self, file_id: &str, destination: &Path) -> Result<(), String> {
+            self.inner.download(file_id, destination).await
+        }
+        async fn quota(&self) -> Result<Option<StorageQuota>, String> {
+            self.inner.quota().await
+        }
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn name_manifest_is_uploaded_once_per_pass_and_retried() {
+        let (mut ctx, root) = context("engine-manifest", serde_json::json!({ "upload": { "max_attempts": 2, "retry_delay_secs": 1 } }));
+        let writes = Arc::new(AtomicUsize::new(0));
+        // Both attempts of the first pass fail
+        let failures = Arc::new(AtomicUsize::new(2));
+        ctx.backend = Box::new(ManifestCounter {
+            inner: LocalBackend::open(root.join("dest").to_str().unwrap()).unwrap(),
+            writes: writes.clone(),
+            failures: failures.clone(),
+        });
+        let key = KeyMaterial::Key([3u8; 32]);
+        let manifest = open_name_manifest(ctx.backend.as_ref(), &root, &key, &ctx.state).await.unwrap();
+        ctx.encryption = Some(EncryptionContext { key, manifest: Some(manifest) });
+        for name in ["manifest-a.txt", "manifest-b.txt", "manifest-c.txt"] {
+            write_local(&root, name, name);
+        }
+
+        let summary = reconcile(&mut ctx, ScanKind::Initial).await;
+        assert_eq!(summary.uploaded, 3);
+        assert_eq!(writes.load(Ordering::SeqCst), 2);
+        assert!(!root.join("dest").join(name_manifest::MANIFEST_NAME).exists());
+        let manifest = ctx.encryption.as_ref().unwrap().manifest.as_ref().unwrap();
+        assert!(manifest.dirty_since.is_some(), "a failed upload stays pending");
+
+        // The next pass has nothing to upload but still delivers the manifest
+        let summary = reconcile(&mut ctx, ScanKind::Periodic).await;
+        assert_eq!((summary.uploaded, summary.up_to_date), (0, 3));
+        assert_eq!(writes.load(Ordering::SeqCst), 3);
+        let uploaded = name_manifest::read_entries(&root.join("dest").join(name_manifest::MANIFEST_NAME), &KeyMaterial::Key([3u8; 32])).unwrap();
+        let mut paths: Vec<&String> = uploaded.values().collect();
+        paths.sort();
+        assert_eq!(paths, ["manifest-a.txt", "manifest-b.txt", "manifest-c.txt"]);
+        assert!(ctx.encryption.as_ref().unwrap().manifest.as_ref().unwrap().dirty_since.is_none());
+
+        // Entries the sync state knows about are restored into a lost manifest
+        fs::remove_file(root.join("name_manifest.enc")).unwrap();
+        fs::remove_file(root.join("dest").join(name_manifest::MANIFEST_NAME)).unwrap();
+        let manifest = open_name_manifest(ctx.backend.as_ref(), &root, &KeyMaterial::Key([3u8; 32]), &ctx.state).await.unwrap();
+        assert!(!manifest.is_empty());
+        assert!(manifest.dirty_since.is_some());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn app_properties_fit_drives_limit_on_char_boundaries() {
+        let short = "C:/Scans/invoice.pdf".to_string();
+        let mut value = short.clone();
+        truncate_app_property("source_path", &mut value);
+        assert_eq!(value, short);
+
+        // Two-, three- and four-byte characters, so every cut point gets tried
+        let hosts = ["ßüäöé".repeat(20), "写真サーバー".repeat(10), "📁🖨".repeat(20)];
+        let paths = ["Belege/Größe/Ära-€.pdf".repeat(8), "書類/スキャン/請求書.pdf".repeat(8)];
+        for (key, original) in hosts
+            .iter()
+            .map(|host| ("source_host", host))
+            .chain(paths.iter().map(|path| ("source_path", path)))
+        {
+            for len in 0..original.len() {
+                if !original.is_char_boundary(len) {
+                    continue;
+                }
+                let mut value = original[..len].to_string();
+                truncate_app_property(key, &mut value);
+                assert!(key.len() + value.len() <= APP_PROPERTY_MAX_BYTES, "{} {:?}", key, value);
+                if len + key.len() <= APP_PROPERTY_MAX_BYTES {
+                    assert_eq!(value, original[..len]);
+                } else {
+                    let tail = value.strip_prefix("...").unwrap();
+                    assert!(original[..len].ends_with(tail));
+                    // At most one character short of the limit
+                    assert!(key.len() + value.len() > APP_PROPERTY_MAX_BYTES - 4, "{:?}", value);
+                }
+            }
+        }
+
+        // A key that leaves no room for a value
+        let mut value = "anything".to_string();
+        truncate_app_property(&"k".repeat(122), &mut value);
+        assert_eq!(value, "");
+    }
+}
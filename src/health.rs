@@ -0,0 +1,170 @@
+use serde::Serialize;
+#[cfg(windows)]
+use std::fs;
+#[cfg(windows)]
+use std::sync::Mutex;
+#[cfg(windows)]
+use std::time::Duration;
+
+#[cfg(windows)]
+use crate::config;
+
+// The startup checks, in the order they run
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthCheck {
+    // config.json and the settings that are validated when the service starts
+    Config,
+    LocalFolder,
+    // Credentials parse, a token can be obtained, the destination can be reached
+    Destination,
+    // The destination folder exists, is a folder and can be written to
+    DestinationAccess,
+}
+
+impl HealthCheck {
+    pub fn label(self) -> &'static str {
+        match self {
+            HealthCheck::Config => "configuration",
+            HealthCheck::LocalFolder => "watched folder",
+            HealthCheck::Destination => "destination connection",
+            HealthCheck::DestinationAccess => "destination folder access",
+        }
+    }
+
+    // Reported to the SCM as the service-specific exit code while degraded
+    pub fn code(self) -> u32 {
+        match self {
+            HealthCheck::Config => 1,
+            HealthCheck::LocalFolder => 2,
+            HealthCheck::Destination => 3,
+            HealthCheck::DestinationAccess => 4,
+        }
+    }
+
+    pub fn failed(self, message: String) -> StartupFailure {
+        StartupFailure { check: self, message }
+    }
+}
+
+pub struct StartupFailure {
+    pub check: HealthCheck,
+    pub message: String,
+}
+
+// One-shot commands report startup failures as plain errors
+impl From<StartupFailure> for String {
+    fn from(failure: StartupFailure) -> String {
+        format!("{} check failed: {}", failure.check.label(), failure.message)
+    }
+}
+
+#[cfg(windows)]
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthState {
+    Starting,
+    Healthy,
+    // Startup checks failed; the supervisor retries them
+    Degraded,
+}
+
+#[cfg(windows)]
+// Written to service_status.json so monitoring and `doctor` can see why the
+// service is not syncing
+#[derive(Serialize, Clone)]
+pub struct HealthStatus {
+    pub state: HealthState,
+    pub since: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_check: Option<HealthCheck>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_retry_secs: Option<u64>,
+}
+
+#[cfg(windows)]
+type Listener = Box<dyn Fn(&HealthStatus) + Send + Sync>;
+
+#[cfg(windows)]
+// Set by the service so status changes reach the SCM
+static LISTENER: once_cell::sync::OnceCell<Listener> = once_cell::sync::OnceCell::new();
+
+#[cfg(windows)]
+// When the current run of failed startup checks began; kept across retries
+static DEGRADED_SINCE: Mutex<Option<String>> = Mutex::new(None);
+
+#[cfg(windows)]
+pub fn set_listener(listener: impl Fn(&HealthStatus) + Send + Sync + 'static) {
+    let _ = LISTENER.set(Box::new(listener));
+}
+
+#[cfg(windows)]
+pub fn report_starting() {
+    report(HealthStatus {
+        state: HealthState::Starting,
+        since: now(),
+        failed_check: None,
+        last_error: None,
+        consecutive_failures: 0,
+        next_retry_secs: None,
+    });
+}
+
+#[cfg(windows)]
+pub fn report_healthy() {
+    *DEGRADED_SINCE.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    report(HealthStatus {
+        state: HealthState::Healthy,
+        since: now(),
+        failed_check: None,
+        last_error: None,
+        consecutive_failures: 0,
+        next_retry_secs: None,
+    });
+}
+
+#[cfg(windows)]
+pub fn report_degraded(failure: &StartupFailure, consecutive_failures: u32, retry_in: Duration) {
+    let since = DEGRADED_SINCE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(now)
+        .clone();
+    report(HealthStatus {
+        state: HealthState::Degraded,
+        since,
+        failed_check: Some(failure.check),
+        last_error: Some(failure.message.clone()),
+        consecutive_failures,
+        next_retry_secs: Some(retry_in.as_secs()),
+    });
+}
+
+#[cfg(windows)]
+fn report(status: HealthStatus) {
+    if let Some(listener) = LISTENER.get() {
+        listener(&status);
+    }
+
+    let path = match config::base_dir() {
+        Ok(dir) => dir.join("service_status.json"),
+        Err(e) => {
+            log::error!("{}", e);
+            return;
+        }
+    };
+    let result = serde_json::to_string_pretty(&status)
+        .map_err(|e| e.to_string())
+        .and_then(|json| fs::write(&path, json).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        log::error!("Failed to write {:?}: {}", path, e);
+    }
+}
+
+#[cfg(windows)]
+fn now() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
@@ -0,0 +1,215 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::SymlinkPolicy;
+
+// Symbolic links are resolved one hop at a time; chains longer than this are
+// treated like a cycle
+const MAX_LINK_HOPS: usize = 40;
+
+// Writes through a temporary file beside `path` and swaps it in, so a crash
+// mid-write leaves the previous copy rather than a truncated one
+pub fn write_atomically(path: &Path, write: impl FnOnce(&mut fs::File) -> Result<(), String>) -> Result<(), String> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let mut file = fs::File::create(&tmp_path).map_err(|e| format!("Failed to create {:?}: {}", tmp_path, e))?;
+    let result = write(&mut file).and_then(|()| {
+        drop(file);
+        fs::rename(&tmp_path, path).map_err(|e| format!("Failed to replace {:?}: {}", path, e))
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+// What a directory entry in the watched folder turned out to be
+pub enum EntryKind {
+    File,
+    Directory,
+    // Removed before it could be looked at
+    Missing,
+    // Not uploaded, with the reason for the log
+    Skipped(String),
+}
+
+// Classifies `path` without following symbolic links unless the policy allows
+// it. Links must resolve to a regular file (inside `root` for FollowWithinRoot);
+// FIFOs, sockets and devices are always skipped.
+pub fn classify(path: &Path, root: &Path, policy: SymlinkPolicy) -> EntryKind {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(m) => m,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return EntryKind::Missing,
+        Err(e) => return EntryKind::Skipped(format!("cannot read metadata: {}", e)),
+    };
+
+    let metadata = if metadata.file_type().is_symlink() {
+        if policy == SymlinkPolicy::Skip {
+            return EntryKind::Skipped("symbolic link".to_string());
+        }
+        let target = match resolve_link(path) {
+            Ok(target) => target,
+            Err(e) => return EntryKind::Skipped(e),
+        };
+        if policy == SymlinkPolicy::FollowWithinRoot {
+            let inside = fs::canonicalize(root).map(|root| target.starts_with(root)).unwrap_or(false);
+            if !inside {
+                return EntryKind::Skipped(format!("symbolic link to {:?}, outside the watched folder", target));
+            }
+        }
+        match fs::metadata(&target) {
+            Ok(m) => m,
+            Err(e) => return EntryKind::Skipped(format!("cannot read link target {:?}: {}", target, e)),
+        }
+    } else {
+        metadata
+    };
+
+    if metadata.is_file() {
+        EntryKind::File
+    } else if metadata.is_dir() {
+        EntryKind::Directory
+    } else {
+        EntryKind::Skipped(special_file_kind(&metadata).to_string())
+    }
+}
+
+// Follows a chain of links to its final target, failing on broken links and cycles
+fn resolve_link(path: &Path) -> Result<PathBuf, String> {
+    let mut visited = HashSet::new();
+    let mut current = path.to_path_buf();
+    while fs::symlink_metadata(&current).map(|m| m.file_type().is_symlink()).unwrap_or(false) {
+        if !visited.insert(current.clone()) || visited.len() > MAX_LINK_HOPS {
+            return Err(format!("symbolic link cycle at {:?}", current));
+        }
+        let target = fs::read_link(&current).map_err(|e| format!("cannot read link {:?}: {}", current, e))?;
+        // Relative targets are relative to the directory containing the link
+        current = match current.parent() {
+            Some(parent) if target.is_relative() => parent.join(target),
+            _ => target,
+        };
+    }
+    fs::canonicalize(&current).map_err(|e| format!("broken symbolic link to {:?}: {}", current, e))
+}
+
+#[cfg(unix)]
+fn special_file_kind(metadata: &fs::Metadata) -> &'static str {
+    use std::os::unix::fs::FileTypeExt;
+    let file_type = metadata.file_type();
+    if file_type.is_fifo() {
+        "FIFO"
+    } else if file_type.is_socket() {
+        "socket"
+    } else if file_type.is_block_device() || file_type.is_char_device() {
+        "device file"
+    } else {
+        "special file"
+    }
+}
+
+#[cfg(not(unix))]
+fn special_file_kind(_metadata: &fs::Metadata) -> &'static str {
+    "special file"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+    #[cfg(unix)]
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn atomic_writes_replace_the_file_or_leave_it_alone() {
+        let dir = temp_dir("local-files-atomic");
+        let path = dir.join("state.json");
+        fs::write(&path, "old").unwrap();
+
+        let error = write_atomically(&path, |file| {
+            std::io::Write::write_all(file, b"partial").unwrap();
+            Err("serializer failed".to_string())
+        });
+        assert_eq!(error, Err("serializer failed".to_string()));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert!(!dir.join("state.json.tmp").exists());
+
+        write_atomically(&path, |file| std::io::Write::write_all(file, b"new").map_err(|e| e.to_string())).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert!(!dir.join("state.json.tmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    fn skipped_reason(kind: EntryKind) -> String {
+        match kind {
+            EntryKind::Skipped(reason) => reason,
+            EntryKind::File => panic!("classified as a file"),
+            EntryKind::Directory => panic!("classified as a directory"),
+            EntryKind::Missing => panic!("classified as missing"),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn link_loops_are_skipped() {
+        let dir = temp_dir("local-files-loop");
+        let root = dir.join("watched");
+        fs::create_dir_all(&root).unwrap();
+        symlink("b.txt", root.join("a.txt")).unwrap();
+        symlink("a.txt", root.join("b.txt")).unwrap();
+        symlink("self.txt", root.join("self.txt")).unwrap();
+
+        for policy in [SymlinkPolicy::Follow, SymlinkPolicy::FollowWithinRoot] {
+            assert!(skipped_reason(classify(&root.join("a.txt"), &root, policy)).contains("cycle"));
+            assert!(skipped_reason(classify(&root.join("self.txt"), &root, policy)).contains("cycle"));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_are_followed_only_as_the_policy_allows() {
+        let dir = temp_dir("local-files-links");
+        let root = dir.join("watched");
+        fs::create_dir_all(root.join("inbox")).unwrap();
+        fs::write(dir.join("outside.txt"), "secret").unwrap();
+        fs::write(root.join("inbox").join("scan.pdf"), "scan").unwrap();
+        symlink(dir.join("outside.txt"), root.join("escape.txt")).unwrap();
+        // Relative, through a second link, climbing out of the root
+        symlink("../outside.txt", root.join("relative-escape.txt")).unwrap();
+        symlink("inbox/scan.pdf", root.join("inside.pdf")).unwrap();
+        symlink("inside.pdf", root.join("chained.pdf")).unwrap();
+
+        let within = SymlinkPolicy::FollowWithinRoot;
+        for name in ["escape.txt", "relative-escape.txt"] {
+            assert!(skipped_reason(classify(&root.join(name), &root, within)).contains("outside the watched folder"), "{}", name);
+            assert!(matches!(classify(&root.join(name), &root, SymlinkPolicy::Follow), EntryKind::File), "{}", name);
+        }
+        for name in ["inside.pdf", "chained.pdf"] {
+            assert!(matches!(classify(&root.join(name), &root, within), EntryKind::File), "{}", name);
+            assert_eq!(skipped_reason(classify(&root.join(name), &root, SymlinkPolicy::Skip)), "symbolic link");
+        }
+
+        symlink("missing.txt", root.join("broken.txt")).unwrap();
+        assert!(skipped_reason(classify(&root.join("broken.txt"), &root, within)).starts_with("broken symbolic link"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fifos_are_skipped() {
+        let dir = temp_dir("local-files-fifo");
+        let fifo = dir.join("pipe");
+        let status = std::process::Command::new("mkfifo").arg(&fifo).status().unwrap();
+        assert!(status.success());
+        symlink("pipe", dir.join("pipe-link")).unwrap();
+
+        assert_eq!(skipped_reason(classify(&fifo, &dir, SymlinkPolicy::FollowWithinRoot)), "FIFO");
+        assert_eq!(skipped_reason(classify(&dir.join("pipe-link"), &dir, SymlinkPolicy::Follow)), "FIFO");
+        assert!(matches!(classify(&dir.join("gone"), &dir, SymlinkPolicy::Follow), EntryKind::Missing));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
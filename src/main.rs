@@ -1,14 +1,20 @@
+#[cfg(windows)]
 use std::sync::mpsc;
 use std::time::Duration;
 use std::sync::Mutex;
 use std::sync::Arc;
+use std::collections::HashSet;
+use std::path::PathBuf;
 use std::fs::OpenOptions;
 use std::io::Write;
 
+#[cfg(windows)]
 use windows_service::service_dispatcher;
+#[cfg(windows)]
 use windows_service::service::{
     ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType,
 };
+#[cfg(windows)]
 use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
 
 // Import logging macros
@@ -16,25 +22,72 @@ use log::{info, error};
 use simplelog::*;
 
 // Import your sync logic module
+mod alerts;
+mod audit_log;
+mod checksum;
+mod cli;
+mod config;
+mod doctor;
+mod encryption;
 mod gdrive_sync;
+mod health;
+mod local_files;
+mod name_manifest;
+mod network;
+mod post_upload;
+mod storage;
+mod sync_state;
+#[cfg(test)]
+mod test_support;
+mod webhooks;
+
+use config::{LogFormat, LoggingConfig};
+#[cfg(windows)]
+use config::WebhookEvent;
 
 const SERVICE_NAME: &str = "GdriveStealthSync";
-const MAX_LOG_SIZE: u64 = 2 * 1024 * 1024; // 2MB
-const MAX_LOG_FILES: usize = 5;
 
 // Global logger for custom rotation
 static ROTATING_LOGGER: once_cell::sync::Lazy<Arc<Mutex<RotatingFileLogger>>> = 
     once_cell::sync::Lazy::new(|| Arc::new(Mutex::new(RotatingFileLogger::new())));
 
+// Rotated log files still being gzipped, which cleanup must leave alone
+static COMPRESSING: once_cell::sync::Lazy<Mutex<HashSet<PathBuf>>> =
+    once_cell::sync::Lazy::new(|| Mutex::new(HashSet::new()));
+
+// Maintenance subcommands run in the console instead of under the SCM
+fn run_console_command() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("decrypt") => std::process::exit(cli::run_decrypt(&args[1..])),
+        Some("sync-once") => std::process::exit(cli::run_sync_once()),
+        Some("test-email") => std::process::exit(cli::run_test_email()),
+        Some("doctor") => std::process::exit(cli::run_doctor()),
+        Some("--dry-run") => std::process::exit(cli::run_dry_run()),
+        _ => {}
+    }
+}
+
+#[cfg(windows)]
 fn main() -> Result<(), windows_service::Error> {
+    run_console_command();
+
     // Register the service with the SCM.
     service_dispatcher::start(SERVICE_NAME, ffi_service_main)?;
     Ok(())
 }
 
+#[cfg(not(windows))]
+fn main() {
+    run_console_command();
+    eprintln!("{} runs as a Windows service; use decrypt, sync-once, test-email, doctor or --dry-run", SERVICE_NAME);
+    std::process::exit(2);
+}
+
+#[cfg(windows)]
 extern "system" fn ffi_service_main(_argc: u32, _argv: *mut *mut u16) {
     // Initialize file-based logging
-    if let Err(e) = initialize_file_logging() {
+    if let Err(e) = initialize_logging(false) {
         eprintln!("Failed to initialize logging: {}", e);
         return;
     }
@@ -83,6 +136,27 @@ extern "system" fn ffi_service_main(_argc: u32, _argv: *mut *mut u16) {
     // Test that logging is working
     info!("GdriveStealthSync service is initializing...");
 
+    // Windows has no "degraded" state, so a failing startup check keeps the
+    // service Running with the check's code as service-specific exit code,
+    // which `sc query` shows as SERVICE_EXIT_CODE
+    health::set_listener(move |status| {
+        let exit_code = match (status.state, status.failed_check) {
+            (health::HealthState::Degraded, Some(check)) => ServiceExitCode::ServiceSpecific(check.code()),
+            _ => ServiceExitCode::Win32(0),
+        };
+        if let Err(e) = status_handle.set_service_status(ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: ServiceState::Running,
+            controls_accepted: ServiceControlAccept::STOP,
+            exit_code,
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        }) {
+            error!("Failed to update service status: {}", e);
+        }
+    });
+
     // --- YOUR CORE LOGIC GOES HERE ---
     let _service_thread = std::thread::spawn(move || {
         match tokio::runtime::Runtime::new() {
@@ -104,6 +178,11 @@ extern "system" fn ffi_service_main(_argc: u32, _argv: *mut *mut u16) {
         error!("Failed to receive shutdown signal: {}", e);
     }
 
+    webhooks::send_blocking(
+        webhooks::Notification::new(WebhookEvent::ServiceStopped, "Service stopped"),
+        Duration::from_secs(10),
+    );
+
     // Tell the SCM that the service is stopped
     if let Err(e) = status_handle.set_service_status(ServiceStatus {
         service_type: ServiceType::OWN_PROCESS,
@@ -120,22 +199,64 @@ extern "system" fn ffi_service_main(_argc: u32, _argv: *mut *mut u16) {
 
 struct RotatingFileLogger {
     log_dir: std::path::PathBuf,
+    format: LogFormat,
+    max_size: u64,
+    max_files: usize,
+    max_total_size: u64,
+    max_age: Option<Duration>,
+    daily_rotation: bool,
+    compress_rotated: bool,
     current_file: Option<std::fs::File>,
+    current_path: Option<std::path::PathBuf>,
     current_size: u64,
+    current_date: chrono::NaiveDate,
 }
 
 impl RotatingFileLogger {
     fn new() -> Self {
         Self {
             log_dir: std::path::PathBuf::new(),
+            format: LogFormat::Text,
+            max_size: 0,
+            max_files: 0,
+            max_total_size: 0,
+            max_age: None,
+            daily_rotation: false,
+            compress_rotated: false,
             current_file: None,
+            current_path: None,
             current_size: 0,
+            current_date: chrono::Utc::now().date_naive(),
         }
     }
 
-    fn initialize(&mut self, log_dir: std::path::PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    fn initialize(&mut self, log_dir: std::path::PathBuf, settings: &LoggingConfig) -> Result<(), Box<dyn std::error::Error>> {
         self.log_dir = log_dir;
+        self.format = settings.format;
+        self.max_size = settings.max_file_size_mb.max(1) * 1024 * 1024;
+        // Always keep at least the file currently being written
+        self.max_files = settings.max_files.max(1);
+        self.max_total_size = settings
+            .max_total_size_mb
+            .map(|mb| mb * 1024 * 1024)
+            .unwrap_or(self.max_size * self.max_files as u64)
+            .max(self.max_size);
+        self.max_age = settings.max_age_days.map(|days| Duration::from_secs(days * 24 * 60 * 60));
+        self.daily_rotation = settings.daily_rotation;
+        self.compress_rotated = settings.compress_rotated;
         std::fs::create_dir_all(&self.log_dir)?;
+
+        self.remove_partial_archives();
+
+        // Files left behind by previous runs are no longer written to
+        if self.compress_rotated {
+            for (path, _, _) in self.find_log_files() {
+                if path.extension().is_some_and(|ext| ext == "log") {
+                    compress_log_file_in_background(path);
+                }
+            }
+        }
+
         self.cleanup_old_log_files();
         self.rotate_if_needed()?;
         Ok(())
@@ -145,7 +266,8 @@ impl RotatingFileLogger {
         let message_bytes = message.as_bytes();
         
         // Check if we need to rotate
-        if self.current_size + message_bytes.len() as u64 > MAX_LOG_SIZE {
+        let day_changed = self.daily_rotation && chrono::Utc::now().date_naive() != self.current_date;
+        if day_changed || self.current_size + message_bytes.len() as u64 > self.max_size {
             self.rotate_if_needed()?;
         }
 
@@ -160,9 +282,45 @@ impl RotatingFileLogger {
         Ok(())
     }
 
+    fn write_record(&mut self, record: &log::Record) -> Result<(), Box<dyn std::error::Error>> {
+        let formatted = match self.format {
+            LogFormat::Text => format!(
+                "{} [{}] {}:{} - {}",
+                chrono::Utc::now().format("%Y-%m-%d %H:%M:%S%.3f UTC"),
+                record.level(),
+                record.file().unwrap_or("unknown"),
+                record.line().unwrap_or(0),
+                record.args()
+            ),
+            LogFormat::Json => {
+                // One JSON object per line; structured key/values attached to the
+                // record (path, drive_file_id, bytes, ...) go under "fields"
+                let mut fields = serde_json::Map::new();
+                let _ = record.key_values().visit(&mut JsonFieldCollector(&mut fields));
+
+                let mut entry = serde_json::Map::new();
+                entry.insert("timestamp".into(), chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true).into());
+                entry.insert("level".into(), record.level().as_str().into());
+                entry.insert("module".into(), record.module_path().unwrap_or("unknown").into());
+                entry.insert("message".into(), record.args().to_string().into());
+                if !fields.is_empty() {
+                    entry.insert("fields".into(), fields.into());
+                }
+                serde_json::Value::Object(entry).to_string()
+            }
+        };
+
+        self.write_log(&formatted)
+    }
+
     fn rotate_if_needed(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         // Close current file
         self.current_file = None;
+        if let Some(closed_path) = self.current_path.take() {
+            if self.compress_rotated {
+                compress_log_file_in_background(closed_path);
+            }
+        }
         
         // Clean up old files
         self.cleanup_old_log_files();
@@ -177,36 +335,140 @@ impl RotatingFileLogger {
             .open(&log_file_path)?;
             
         self.current_file = Some(file);
+        self.current_path = Some(log_file_path);
         self.current_size = 0;
+        self.current_date = chrono::Utc::now().date_naive();
         
         Ok(())
     }
 
-    fn cleanup_old_log_files(&self) {
+    // Plain and compressed log files, with their modification times and sizes
+    fn find_log_files(&self) -> Vec<(std::path::PathBuf, std::time::SystemTime, u64)> {
         let mut log_files = Vec::new();
-        
-        // Find all log files
+
         if let Ok(entries) = std::fs::read_dir(&self.log_dir) {
             for entry in entries.flatten() {
                 let path = entry.path();
                 if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                    if name.starts_with("gdrive_sync_") && name.ends_with(".log") {
+                    if name.starts_with("gdrive_sync_") && (name.ends_with(".log") || name.ends_with(".log.gz")) {
                         if let Ok(metadata) = entry.metadata() {
-                            log_files.push((path, metadata.modified().unwrap_or(std::time::SystemTime::UNIX_EPOCH)));
+                            let modified = metadata.modified().unwrap_or(std::time::SystemTime::UNIX_EPOCH);
+                            log_files.push((path, modified, metadata.len()));
                         }
                     }
                 }
             }
         }
+
+        log_files
+    }
+
+    // Archives a previous run was still writing when it stopped
+    fn remove_partial_archives(&self) {
+        if let Ok(entries) = std::fs::read_dir(&self.log_dir) {
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                if name.starts_with("gdrive_sync_") && name.ends_with(".log.gz.tmp") {
+                    let _ = std::fs::remove_file(entry.path());
+                }
+            }
+        }
+    }
+
+    fn cleanup_old_log_files(&self) {
+        // Find all log files, except those being compressed right now; their
+        // archives are counted by the next cleanup
+        let mut log_files = self.find_log_files();
+        let compressing = COMPRESSING.lock().unwrap_or_else(|e| e.into_inner());
+        log_files.retain(|(path, _, _)| !compressing.contains(path));
+        drop(compressing);
         
         // Sort by modification time (oldest first)
-        log_files.sort_by_key(|(_, time)| *time);
+        log_files.sort_by_key(|(_, time, _)| *time);
         
-        // Remove excess files
-        while log_files.len() >= MAX_LOG_FILES {
-            let (path, _) = log_files.remove(0);
+        // Remove files past the maximum age
+        if let Some(max_age) = self.max_age {
+            let now = std::time::SystemTime::now();
+            log_files.retain(|(path, modified, _)| {
+                let expired = now.duration_since(*modified).map(|age| age > max_age).unwrap_or(false);
+                if expired {
+                    let _ = std::fs::remove_file(path);
+                }
+                !expired
+            });
+        }
+
+        // Remove excess files. Gzipped files only count toward the size budget,
+        // so compression keeps more history in the same disk space.
+        let counted = |path: &std::path::Path| !self.compress_rotated || path.extension().is_some_and(|ext| ext == "log");
+        while log_files.iter().filter(|(path, _, _)| counted(path)).count() >= self.max_files {
+            let index = log_files.iter().position(|(path, _, _)| counted(path)).unwrap_or(0);
+            let (path, _, _) = log_files.remove(index);
             let _ = std::fs::remove_file(&path);
         }
+
+        // Leave room in the budget for the file about to be started
+        let budget = self.max_total_size.saturating_sub(self.max_size);
+        let mut total: u64 = log_files.iter().map(|(_, _, size)| size).sum();
+        while total > budget && !log_files.is_empty() {
+            let (path, _, size) = log_files.remove(0);
+            let _ = std::fs::remove_file(&path);
+            total -= size;
+        }
+    }
+}
+
+// Gzips a closed log file off the logging path. The archive keeps the original
+// modification time so age-based cleanup and ordering are unaffected.
+fn compress_log_file_in_background(path: PathBuf) {
+    if !COMPRESSING.lock().unwrap_or_else(|e| e.into_inner()).insert(path.clone()) {
+        return;
+    }
+    std::thread::spawn(move || {
+        let result = gzip_log_file(&path);
+        COMPRESSING.lock().unwrap_or_else(|e| e.into_inner()).remove(&path);
+        if let Err(e) = result {
+            error!("Failed to compress rotated log file {:?}: {}", path, e);
+        }
+    });
+}
+
+fn gzip_log_file(path: &std::path::Path) -> std::io::Result<()> {
+    let modified = std::fs::metadata(path)?.modified()?;
+    let gz_path = path.with_extension("log.gz");
+    // Written under a temporary name so cleanup never counts a partial archive
+    let tmp_path = path.with_extension("log.gz.tmp");
+
+    let mut input = std::fs::File::open(path)?;
+    let output = std::fs::File::create(&tmp_path)?;
+    let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+    std::io::copy(&mut input, &mut encoder)?;
+    let output = encoder.finish()?;
+    output.set_modified(modified)?;
+    drop(output);
+    drop(input);
+
+    std::fs::rename(&tmp_path, &gz_path)?;
+    std::fs::remove_file(path)
+}
+
+// Converts log key/value pairs into JSON values, keeping numbers and booleans typed
+struct JsonFieldCollector<'a>(&'a mut serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> log::kv::VisitSource<'kvs> for JsonFieldCollector<'_> {
+    fn visit_pair(&mut self, key: log::kv::Key<'kvs>, value: log::kv::Value<'kvs>) -> Result<(), log::kv::Error> {
+        let json_value = if let Some(n) = value.to_u64() {
+            serde_json::Value::from(n)
+        } else if let Some(n) = value.to_i64() {
+            serde_json::Value::from(n)
+        } else if let Some(b) = value.to_bool() {
+            serde_json::Value::from(b)
+        } else {
+            serde_json::Value::from(value.to_string())
+        };
+        self.0.insert(key.as_str().to_string(), json_value);
+        Ok(())
     }
 }
 
@@ -227,20 +489,42 @@ impl Write for RotatingFileLogger {
     }
 }
 
-fn initialize_file_logging() -> Result<(), Box<dyn std::error::Error>> {
-    // Get the directory where the executable is located
-    let exe_path = std::env::current_exe()?;
-    let log_dir = exe_path.parent().unwrap().join("logs");
-    
+// Console commands also echo log output to the terminal
+fn initialize_logging(console: bool) -> Result<(), Box<dyn std::error::Error>> {
+    // Logging settings come from config.json; a missing or broken config falls back
+    // to the defaults here and is reported by the sync loop once logging is up
+    let logging = config::load_config().map(|c| c.logging).unwrap_or_default();
+    let log_dir = logging.log_dir()?;
+
     // Initialize the rotating logger
-    ROTATING_LOGGER.lock().unwrap().initialize(log_dir)?;
+    ROTATING_LOGGER.lock().unwrap().initialize(log_dir, &logging)?;
 
     // Create a custom logger that writes through our rotating file logger
-    struct CustomLogger;
+    struct CustomLogger {
+        level: LevelFilter,
+        // Sorted longest prefix first so the most specific override wins
+        module_levels: Vec<(String, LevelFilter)>,
+    }
+
+    impl CustomLogger {
+        fn level_for(&self, target: &str) -> LevelFilter {
+            self.module_levels
+                .iter()
+                .find(|(prefix, _)| {
+                    target == prefix || target.starts_with(&format!("{}::", prefix))
+                })
+                .map(|(_, level)| *level)
+                .unwrap_or(self.level)
+        }
+    }
     
     impl SharedLogger for CustomLogger {
         fn level(&self) -> LevelFilter {
-            LevelFilter::Info
+            // Most verbose level in use, so per-module overrides are not filtered out globally
+            self.module_levels
+                .iter()
+                .map(|(_, level)| *level)
+                .fold(self.level, |max, level| max.max(level))
         }
 
         fn config(&self) -> Option<&Config> {
@@ -254,22 +538,13 @@ fn initialize_file_logging() -> Result<(), Box<dyn std::error::Error>> {
 
     impl log::Log for CustomLogger {
         fn enabled(&self, metadata: &log::Metadata) -> bool {
-            metadata.level() <= log::Level::Info
+            metadata.level() <= self.level_for(metadata.target())
         }
 
         fn log(&self, record: &log::Record) {
             if self.enabled(record.metadata()) {
-                let formatted = format!(
-                    "{} [{}] {}:{} - {}",
-                    chrono::Utc::now().format("%Y-%m-%d %H:%M:%S%.3f UTC"),
-                    record.level(),
-                    record.file().unwrap_or("unknown"),
-                    record.line().unwrap_or(0),
-                    record.args()
-                );
-                
                 if let Ok(mut logger) = ROTATING_LOGGER.lock() {
-                    let _ = logger.write_log(&formatted);
+                    let _ = logger.write_record(record);
                 }
             }
         }
@@ -281,8 +556,18 @@ fn initialize_file_logging() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    let mut module_levels: Vec<(String, LevelFilter)> = logging.modules.into_iter().collect();
+    module_levels.sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+
     // Initialize the logger
-    CombinedLogger::init(vec![Box::new(CustomLogger)])?;
+    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![Box::new(CustomLogger {
+        level: logging.level,
+        module_levels,
+    })];
+    if console {
+        loggers.push(TermLogger::new(logging.level, Config::default(), TerminalMode::Mixed, ColorChoice::Auto));
+    }
+    CombinedLogger::init(loggers)?;
 
     Ok(())
 }
@@ -294,4 +579,117 @@ pub fn log_info(message: &str) {
 
 pub fn log_error(message: &str) {
     error!("{}", message);
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logger_in(name: &str, compress_rotated: bool) -> RotatingFileLogger {
+        let dir = test_support::temp_dir(name);
+        let mut logger = RotatingFileLogger::new();
+        logger.log_dir = dir;
+        logger.max_size = 1000;
+        logger.max_files = 3;
+        logger.max_total_size = 3000;
+        logger.compress_rotated = compress_rotated;
+        logger
+    }
+
+    // Files numbered oldest first, `size` bytes each
+    fn write_log_files(logger: &RotatingFileLogger, extension: &str, count: usize, size: usize) {
+        let start = std::time::SystemTime::now() - Duration::from_secs(3600);
+        for i in 0..count {
+            let path = logger.log_dir.join(format!("gdrive_sync_20240101_{:06}.{}", i, extension));
+            let file = std::fs::File::create(&path).unwrap();
+            file.set_len(size as u64).unwrap();
+            file.set_modified(start + Duration::from_secs(i as u64)).unwrap();
+        }
+    }
+
+    fn remaining(logger: &RotatingFileLogger) -> Vec<String> {
+        let mut names: Vec<String> = logger
+            .find_log_files()
+            .into_iter()
+            .map(|(path, _, _)| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn compressed_files_are_limited_by_size_not_count() {
+        let logger = logger_in("log-retention-compressed", true);
+        write_log_files(&logger, "log.gz", 30, 50);
+
+        logger.cleanup_old_log_files();
+
+        // 1500 bytes of archives fit in the 2000 left beside the next file
+        assert_eq!(remaining(&logger).len(), 30);
+        std::fs::remove_dir_all(&logger.log_dir).unwrap();
+    }
+
+    #[test]
+    fn oldest_files_go_first_when_over_the_size_budget() {
+        let logger = logger_in("log-retention-budget", true);
+        write_log_files(&logger, "log.gz", 10, 300);
+
+        logger.cleanup_old_log_files();
+
+        let names = remaining(&logger);
+        assert_eq!(names.len(), 6);
+        assert_eq!(names[0], "gdrive_sync_20240101_000004.log.gz");
+        std::fs::remove_dir_all(&logger.log_dir).unwrap();
+    }
+
+    #[test]
+    fn files_being_compressed_are_left_alone() {
+        let logger = logger_in("log-retention-compressing", true);
+        write_log_files(&logger, "log", 5, 10);
+        let in_flight = logger.log_dir.join("gdrive_sync_20240101_000000.log");
+        COMPRESSING.lock().unwrap().insert(in_flight.clone());
+
+        logger.cleanup_old_log_files();
+        COMPRESSING.lock().unwrap().remove(&in_flight);
+
+        assert_eq!(
+            remaining(&logger),
+            [
+                "gdrive_sync_20240101_000000.log",
+                "gdrive_sync_20240101_000003.log",
+                "gdrive_sync_20240101_000004.log"
+            ]
+        );
+        std::fs::remove_dir_all(&logger.log_dir).unwrap();
+    }
+
+    #[test]
+    fn partial_archives_are_removed() {
+        let logger = logger_in("log-partial-archives", true);
+        write_log_files(&logger, "log", 1, 10);
+        write_log_files(&logger, "log.gz.tmp", 1, 10);
+
+        logger.remove_partial_archives();
+
+        let mut names: Vec<String> = std::fs::read_dir(&logger.log_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["gdrive_sync_20240101_000000.log"]);
+        std::fs::remove_dir_all(&logger.log_dir).unwrap();
+    }
+
+    #[test]
+    fn without_compression_every_file_counts() {
+        let logger = logger_in("log-retention-plain", false);
+        write_log_files(&logger, "log", 5, 10);
+
+        logger.cleanup_old_log_files();
+
+        assert_eq!(
+            remaining(&logger),
+            ["gdrive_sync_20240101_000003.log", "gdrive_sync_20240101_000004.log"]
+        );
+        std::fs::remove_dir_all(&logger.log_dir).unwrap();
+    }
+}
@@ -0,0 +1,120 @@
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::encryption::{self, KeyMaterial};
+use crate::local_files;
+
+// Name of the encrypted manifest stored in the Drive folder next to the files
+pub const MANIFEST_NAME: &str = "gdrive_sync_manifest.enc";
+
+// Maps deterministic remote names back to the original relative paths. The manifest
+// is only ever written encrypted, both locally and on Drive.
+#[derive(Serialize, Deserialize)]
+struct ManifestData {
+    version: u32,
+    // remote name -> relative path using '/' separators
+    entries: BTreeMap<String, String>,
+}
+
+pub struct NameManifest {
+    local_path: PathBuf,
+    name_key: [u8; 32],
+    entries: BTreeMap<String, String>,
+    // Drive file ID of the uploaded manifest, once known
+    pub remote_id: Option<String>,
+    // First change not yet saved and uploaded; the manifest is written once per
+    // pass rather than after every file
+    pub dirty_since: Option<Instant>,
+}
+
+impl NameManifest {
+    pub fn open(local_path: PathBuf, key: &KeyMaterial) -> Result<Self, String> {
+        let entries = if local_path.exists() {
+            read_entries(&local_path, key)?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(NameManifest {
+            local_path,
+            name_key: key.derive_name_key(),
+            entries,
+            remote_id: None,
+            dirty_since: None,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn local_path(&self) -> &Path {
+        &self.local_path
+    }
+
+    // The same relative path always maps to the same remote name
+    pub fn remote_name(&self, relative_path: &str) -> String {
+        let mut mac = <Hmac<sha2::Sha256> as Mac>::new_from_slice(&self.name_key).expect("HMAC accepts any key length");
+        mac.update(normalize(relative_path).as_bytes());
+        let digest = format!("{:x}", mac.finalize().into_bytes());
+        format!("{}.{}", &digest[..32], encryption::ENCRYPTED_EXTENSION)
+    }
+
+    // Returns true when the manifest changed and needs to be saved and re-uploaded
+    pub fn record(&mut self, remote_name: &str, relative_path: &str) -> bool {
+        let relative_path = normalize(relative_path);
+        if self.entries.get(remote_name) == Some(&relative_path) {
+            return false;
+        }
+        self.entries.insert(remote_name.to_string(), relative_path);
+        self.dirty_since.get_or_insert_with(Instant::now);
+        true
+    }
+
+    // Adds entries from another copy (e.g. the one already on Drive) without
+    // overwriting local ones; marks the manifest for upload if that copy lacks
+    // local entries
+    pub fn merge_from(&mut self, encrypted_path: &Path, key: &KeyMaterial) -> Result<usize, String> {
+        let other = read_entries(encrypted_path, key)?;
+        if self.entries.keys().any(|remote_name| !other.contains_key(remote_name)) {
+            self.dirty_since.get_or_insert_with(Instant::now);
+        }
+        let mut added = 0;
+        for (remote_name, relative_path) in other {
+            if let Entry::Vacant(entry) = self.entries.entry(remote_name) {
+                entry.insert(relative_path);
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    pub fn save(&self, key: &KeyMaterial) -> Result<(), String> {
+        let data = ManifestData { version: 1, entries: self.entries.clone() };
+        let json = serde_json::to_vec(&data).map_err(|e| format!("Failed to serialize name manifest: {}", e))?;
+        local_files::write_atomically(&self.local_path, |file| {
+            encryption::encrypt_stream(json.as_slice(), BufWriter::new(file), key)
+                .map_err(|e| format!("Failed to encrypt name manifest: {}", e))
+        })
+        .map_err(|e| format!("Failed to save name manifest {:?}: {}", self.local_path, e))
+    }
+}
+
+// Decrypts a manifest file and returns its remote name -> relative path entries
+pub fn read_entries(path: &Path, key: &KeyMaterial) -> Result<BTreeMap<String, String>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open name manifest {:?}: {}", path, e))?;
+    let mut json = Vec::new();
+    encryption::decrypt_stream(file, &mut json, key)
+        .map_err(|e| format!("Failed to decrypt name manifest {:?}: {}", path, e))?;
+    let data: ManifestData = serde_json::from_slice(&json)
+        .map_err(|e| format!("Failed to parse name manifest {:?}: {}", path, e))?;
+    Ok(data.entries)
+}
+
+fn normalize(relative_path: &str) -> String {
+    relative_path.replace('\\', "/")
+}
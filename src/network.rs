@@ -0,0 +1,312 @@
+use base64::Engine;
+use hyper::Uri;
+use hyper_rustls::HttpsConnector;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioIo;
+use std::fs;
+use std::net::IpAddr;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tower_service::Service;
+
+use crate::config::{self, NetworkConfig};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+// Connector for every outgoing HTTPS client (Drive, token requests, S3, WebDAV,
+// webhooks), so the proxy and CA settings apply everywhere
+pub type Connector = HttpsConnector<ProxyConnector>;
+
+// Largest proxy response head accepted for a CONNECT request
+const MAX_CONNECT_RESPONSE: usize = 16 * 1024;
+
+pub fn connector(network: &NetworkConfig) -> Result<Connector, String> {
+    let mut http = HttpConnector::new();
+    // TLS is added on top by HttpsConnector, so https URIs must get through
+    http.enforce_http(false);
+
+    let proxy = match &network.proxy {
+        Some(settings) => {
+            let uri = settings
+                .url
+                .parse::<Uri>()
+                .map_err(|e| format!("Invalid proxy URL '{}': {}", settings.url, e))?;
+            if uri.scheme_str() != Some("http") || uri.host().is_none() {
+                return Err(format!("Invalid proxy URL '{}': expected http://host:port", settings.url));
+            }
+            let authorization = settings.username.as_ref().map(|username| {
+                let credentials = format!("{}:{}", username, settings.password.as_deref().unwrap_or_default());
+                format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(credentials))
+            });
+            Some(Arc::new(Proxy {
+                uri,
+                authorization,
+                no_proxy: settings.no_proxy.iter().map(|entry| entry.trim().to_lowercase()).collect(),
+            }))
+        }
+        None => None,
+    };
+
+    Ok(hyper_rustls::HttpsConnectorBuilder::new()
+        .with_tls_config(tls_config(network)?)
+        .https_or_http()
+        .enable_http1()
+        .wrap_connector(ProxyConnector { http, proxy }))
+}
+
+// The system's trusted roots plus any configured PEM bundles, e.g. the root of
+// a TLS-inspecting proxy
+fn tls_config(network: &NetworkConfig) -> Result<rustls::ClientConfig, String> {
+    let mut roots = rustls::RootCertStore::empty();
+    let native = rustls_native_certs::load_native_certs();
+    for e in &native.errors {
+        log::error!("Failed to load a system CA certificate: {}", e);
+    }
+    roots.add_parsable_certificates(native.certs);
+
+    for bundle in &network.ca_bundles {
+        let path = config::resolve_path(bundle)?;
+        let pem = fs::read(&path).map_err(|e| format!("Failed to read CA bundle {:?}: {}", path, e))?;
+        let certs = rustls_pemfile::certs(&mut pem.as_slice())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to parse CA bundle {:?}: {}", path, e))?;
+        if certs.is_empty() {
+            return Err(format!("CA bundle {:?} contains no certificates", path));
+        }
+        for cert in certs {
+            roots
+                .add(cert)
+                .map_err(|e| format!("Invalid certificate in CA bundle {:?}: {}", path, e))?;
+        }
+    }
+
+    Ok(rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth())
+}
+
+struct Proxy {
+    uri: Uri,
+    authorization: Option<String>,
+    no_proxy: Vec<String>,
+}
+
+impl Proxy {
+    // Entries match the host itself or any subdomain ("example.com" and
+    // ".example.com" both cover "api.example.com"), optionally only on one
+    // port ("example.com:8443"); IP addresses match exactly and "*" disables
+    // the proxy
+    fn bypasses(&self, host: &str, port: u16) -> bool {
+        let host = host.trim_start_matches('[').trim_end_matches(']').to_lowercase();
+        self.no_proxy.iter().any(|entry| {
+            if entry == "*" {
+                return true;
+            }
+            let (entry_host, entry_port) = split_port(entry);
+            if entry_port.is_some_and(|entry_port| entry_port != port) {
+                return false;
+            }
+            if entry_host.parse::<IpAddr>().is_ok() || host.parse::<IpAddr>().is_ok() {
+                return host == entry_host;
+            }
+            let domain = entry_host.trim_start_matches("*.").trim_start_matches('.');
+            host == domain || host.ends_with(&format!(".{}", domain))
+        })
+    }
+}
+
+// "host:port" or "[v6]:port" into its parts; bare IPv6 addresses have no port
+fn split_port(entry: &str) -> (&str, Option<u16>) {
+    if let Some(rest) = entry.strip_prefix('[') {
+        return match rest.split_once(']') {
+            Some((host, port)) => (host, port.strip_prefix(':').and_then(|port| port.parse().ok())),
+            None => (rest, None),
+        };
+    }
+    match entry.split_once(':') {
+        Some((host, port)) if !port.contains(':') => (host, port.parse().ok()),
+        _ => (entry, None),
+    }
+}
+
+fn default_port(destination: &Uri) -> u16 {
+    destination
+        .port_u16()
+        .unwrap_or(if destination.scheme_str() == Some("http") { 80 } else { 443 })
+}
+
+// Connects directly, or through an HTTP proxy with a CONNECT tunnel
+#[derive(Clone)]
+pub struct ProxyConnector {
+    http: HttpConnector,
+    proxy: Option<Arc<Proxy>>,
+}
+
+impl Service<Uri> for ProxyConnector {
+    type Response = TokioIo<TcpStream>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, destination: Uri) -> Self::Future {
+        let mut http = self.http.clone();
+        let proxy = self
+            .proxy
+            .clone()
+            .filter(|proxy| !proxy.bypasses(destination.host().unwrap_or_default(), default_port(&destination)));
+        Box::pin(async move {
+            match proxy {
+                Some(proxy) => tunnel(http, &proxy, &destination).await,
+                None => http.call(destination).await.map_err(Into::into),
+            }
+        })
+    }
+}
+
+async fn tunnel(mut http: HttpConnector, proxy: &Proxy, destination: &Uri) -> Result<TokioIo<TcpStream>, BoxError> {
+    let host = destination.host().ok_or("destination URL has no host")?;
+    let port = default_port(destination);
+    let mut stream = http
+        .call(proxy.uri.clone())
+        .await
+        .map_err(|e| format!("Failed to connect to proxy {}: {}", proxy.uri, e))?
+        .into_inner();
+
+    let mut request = format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n");
+    if let Some(authorization) = &proxy.authorization {
+        request.push_str(&format!("Proxy-Authorization: {}\r\n", authorization));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // The server sends nothing before the TLS handshake, so everything read
+    // here belongs to the proxy's response
+    let mut head = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 || head.len() > MAX_CONNECT_RESPONSE {
+            return Err(format!("Proxy {} closed the connection during CONNECT", proxy.uri).into());
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let status_line = head.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.len() == 3 && status.starts_with('2') => Ok(TokioIo::new(stream)),
+        Some("407") => Err(format!("Proxy {} requires authentication: {}", proxy.uri, status_line).into()),
+        _ => Err(format!("Proxy {} refused CONNECT to {}:{}: {}", proxy.uri, host, port, status_line).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn proxy(uri: &str, no_proxy: &[&str], authorization: Option<&str>) -> Proxy {
+        Proxy {
+            uri: uri.parse().unwrap(),
+            authorization: authorization.map(str::to_string),
+            no_proxy: no_proxy.iter().map(|entry| entry.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn bypass_entries_match_hosts_suffixes_ports_and_addresses() {
+        let bare = proxy("http://proxy:3128", &["example.com"], None);
+        assert!(bare.bypasses("example.com", 443));
+        assert!(bare.bypasses("API.Example.com", 443));
+        assert!(!bare.bypasses("badexample.com", 443));
+        assert!(!bare.bypasses("example.com.evil.net", 443));
+
+        let suffix = proxy("http://proxy:3128", &[".corp.example", "*.internal"], None);
+        assert!(suffix.bypasses("files.corp.example", 443));
+        assert!(suffix.bypasses("corp.example", 443));
+        assert!(suffix.bypasses("dav.internal", 80));
+        assert!(!suffix.bypasses("corp.example.com", 443));
+
+        assert!(proxy("http://proxy:3128", &["*"], None).bypasses("anything.test", 443));
+
+        let ported = proxy("http://proxy:3128", &["dav.example:8443", "[::1]:8080"], None);
+        assert!(ported.bypasses("dav.example", 8443));
+        assert!(!ported.bypasses("dav.example", 443));
+        assert!(ported.bypasses("[::1]", 8080));
+        assert!(!ported.bypasses("[::1]", 443));
+
+        let addresses = proxy("http://proxy:3128", &["10.0.0.5", "::1", "0.5"], None);
+        assert!(addresses.bypasses("10.0.0.5", 443));
+        assert!(addresses.bypasses("[::1]", 443));
+        assert!(!addresses.bypasses("10.0.0.50", 443));
+        assert!(!addresses.bypasses("192.168.0.5", 443));
+    }
+
+    // Accepts one connection, returns the request head it received and
+    // answers with `response`
+    async fn stand_in_proxy(response: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+            let mut buffer = [0u8; 1024];
+            while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).await.unwrap();
+                assert!(read > 0, "client closed before finishing the request");
+                head.extend_from_slice(&buffer[..read]);
+            }
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8(head).unwrap()
+        });
+        (uri, server)
+    }
+
+    async fn connect_through(response: &'static str, authorization: Option<&str>) -> (Result<(), String>, String) {
+        let (uri, server) = stand_in_proxy(response).await;
+        let proxy = proxy(&uri, &[], authorization);
+        let destination: Uri = "https://files.example:8443/upload".parse().unwrap();
+        let result = tunnel(HttpConnector::new(), &proxy, &destination)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string());
+        (result, server.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn tunnel_accepts_a_successful_connect() {
+        let (result, request) = connect_through("HTTP/1.1 200 Connection established\r\n\r\n", None).await;
+        assert_eq!(result, Ok(()));
+        assert!(request.starts_with("CONNECT files.example:8443 HTTP/1.1\r\nHost: files.example:8443\r\n"));
+        assert!(!request.to_lowercase().contains("proxy-authorization"));
+    }
+
+    #[tokio::test]
+    async fn tunnel_sends_proxy_credentials() {
+        let (result, request) = connect_through("HTTP/1.0 200 OK\r\nVia: test\r\n\r\n", Some("Basic dXNlcjpwYXNz")).await;
+        assert_eq!(result, Ok(()));
+        assert!(request.contains("\r\nProxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+    }
+
+    #[tokio::test]
+    async fn tunnel_reports_missing_proxy_authentication() {
+        let response = "HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Basic\r\n\r\n";
+        let (result, _) = connect_through(response, None).await;
+        assert!(result.unwrap_err().contains("requires authentication"));
+    }
+
+    #[tokio::test]
+    async fn tunnel_rejects_malformed_status_lines() {
+        for response in ["garbage\r\n\r\n", "HTTP/1.1 2\r\n\r\n", "HTTP/1.1 403 Forbidden\r\n\r\n"] {
+            let (result, _) = connect_through(response, None).await;
+            assert!(result.unwrap_err().contains("refused CONNECT to files.example:8443"), "{:?}", response);
+        }
+    }
+}
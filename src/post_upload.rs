@@ -0,0 +1,161 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::config::{self, AfterUploadAction, AfterUploadConfig};
+
+// Size and modification time of a file when it was hashed for upload
+pub struct Snapshot {
+    pub size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Snapshot {
+    pub fn of(metadata: &fs::Metadata) -> Self {
+        Snapshot {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
+    }
+
+    fn matches(&self, file_path: &Path) -> bool {
+        fs::metadata(file_path).is_ok_and(|m| m.len() == self.size && m.modified().ok() == self.modified)
+    }
+}
+
+// Runs the configured after_upload action on a file that Drive has confirmed.
+// Callers must only invoke this once the remote size and md5 match the local file.
+// A file written to since `snapshot` was taken is left alone, since the verified
+// copy does not hold the new content.
+pub fn apply(file_path: &Path, snapshot: &Snapshot, settings: &AfterUploadConfig) -> Result<(), String> {
+    if settings.action != AfterUploadAction::Keep && !snapshot.matches(file_path) {
+        log::info!("{:?} changed after it was hashed for upload; leaving it in place", file_path);
+        return Ok(());
+    }
+    match settings.action {
+        AfterUploadAction::Keep => Ok(()),
+        AfterUploadAction::Delete => fs::remove_file(file_path)
+            .map_err(|e| format!("Failed to delete {:?}: {}", file_path, e)),
+        AfterUploadAction::Move => {
+            let destination = archive_destination(file_path, settings)?;
+            if let Err(e) = move_file(file_path, &destination) {
+                // Drop the placeholder that reserved the name
+                let _ = fs::remove_file(&destination);
+                return Err(e);
+            }
+            log::info!("Moved {:?} to archive {:?}", file_path, destination);
+            Ok(())
+        }
+    }
+}
+
+// Reserves a free name in the archive folder by creating an empty placeholder,
+// which the move then replaces
+fn archive_destination(file_path: &Path, settings: &AfterUploadConfig) -> Result<PathBuf, String> {
+    let archive_dir = settings
+        .archive_dir
+        .as_ref()
+        .ok_or_else(|| "after_upload action is \"move\" but no archive_dir is configured".to_string())?;
+
+    let mut dir = config::resolve_path(archive_dir)?;
+    if settings.date_subfolders {
+        let today = chrono::Local::now();
+        dir = dir
+            .join(today.format("%Y").to_string())
+            .join(today.format("%m").to_string())
+            .join(today.format("%d").to_string());
+    }
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create archive folder {:?}: {}", dir, e))?;
+
+    let file_name = file_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| format!("Failed to get file name from path: {:?}", file_path))?;
+
+    // Never overwrite an earlier archived file with the same name: try the name
+    // itself, then a timestamp suffix, then the timestamp with a counter
+    let stem = file_path.file_stem().and_then(|s| s.to_str()).unwrap_or("file");
+    let extension = file_path.extension().and_then(|e| e.to_str());
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+    for attempt in 0u32.. {
+        let name = match (attempt, extension) {
+            (0, _) => file_name.to_string(),
+            (1, Some(ext)) => format!("{}_{}.{}", stem, timestamp, ext),
+            (1, None) => format!("{}_{}", stem, timestamp),
+            (n, Some(ext)) => format!("{}_{}_{}.{}", stem, timestamp, n, ext),
+            (n, None) => format!("{}_{}_{}", stem, timestamp, n),
+        };
+        let destination = dir.join(name);
+        match fs::OpenOptions::new().write(true).create_new(true).open(&destination) {
+            Ok(_) => return Ok(destination),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("Failed to create {:?}: {}", destination, e)),
+        }
+    }
+    unreachable!("the archive folder cannot hold every suffix")
+}
+
+fn move_file(from: &Path, to: &Path) -> Result<(), String> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    // rename fails across volumes, so fall back to copy + delete
+    fs::copy(from, to).map_err(|e| format!("Failed to copy {:?} to {:?}: {}", from, to, e))?;
+    fs::remove_file(from).map_err(|e| format!("Copied to archive but failed to delete {:?}: {}", from, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn settings(action: &str, archive_dir: &Path) -> AfterUploadConfig {
+        serde_json::from_value(serde_json::json!({ "action": action, "archive_dir": archive_dir })).unwrap()
+    }
+
+    fn snapshot(path: &Path) -> Snapshot {
+        Snapshot::of(&fs::metadata(path).unwrap())
+    }
+
+    #[test]
+    fn leaves_files_that_changed_after_hashing() {
+        let dir = temp_dir("post-upload-changed");
+        let path = dir.join("scan.log");
+        fs::write(&path, "first page\n").unwrap();
+        let hashed = snapshot(&path);
+
+        // A writer appends after the upload was hashed and verified
+        fs::write(&path, "first page\nsecond page\n").unwrap();
+        apply(&path, &hashed, &settings("delete", &dir.join("archive"))).unwrap();
+        apply(&path, &hashed, &settings("move", &dir.join("archive"))).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first page\nsecond page\n");
+        assert!(!dir.join("archive").join("scan.log").exists());
+
+        apply(&path, &snapshot(&path), &settings("delete", &dir.join("archive"))).unwrap();
+        assert!(!path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn archives_same_named_files_without_overwriting() {
+        let dir = temp_dir("post-upload-archive");
+        let archive = dir.join("archive");
+        // All within the same second, so the timestamp suffix alone would collide
+        for content in ["one", "two", "three", "four"] {
+            let path = dir.join("report.txt");
+            fs::write(&path, content).unwrap();
+            apply(&path, &snapshot(&path), &settings("move", &archive)).unwrap();
+            assert!(!path.exists());
+        }
+
+        let mut archived: Vec<String> = fs::read_dir(&archive)
+            .unwrap()
+            .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect();
+        archived.sort();
+        assert_eq!(archived, ["four", "one", "three", "two"]);
+        assert_eq!(fs::read_to_string(archive.join("report.txt")).unwrap(), "one");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,296 @@
+use async_trait::async_trait;
+use google_drive3::{api, DriveHub, yup_oauth2::ServiceAccountKey};
+use std::fs;
+use std::path::Path;
+
+use super::{RemoteFile, StorageBackend, StorageQuota, UploadMetadata};
+use crate::config::Config;
+use crate::log_info;
+use crate::network;
+
+type Hub = DriveHub<network::Connector>;
+
+const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+const DRIVE_SCOPE: &str = "https://www.googleapis.com/auth/drive";
+// Fields requested for every file, enough to verify an upload
+const FILE_FIELDS: &str = "id,name,size,md5Checksum";
+
+// Google Drive, authenticated with the service account in credentials.json
+pub struct DriveBackend {
+    hub: Hub,
+    folder_id: String,
+}
+
+impl DriveBackend {
+    pub async fn connect(base_dir: &Path, config: &Config) -> Result<Self, String> {
+        let folder_id = config.gdrive_folder_id.as_str();
+        if folder_id.is_empty() {
+            return Err("gdrive_folder_id is not set in config.json".to_string());
+        }
+
+        let creds_path = base_dir.join("credentials.json");
+        let secret_json = fs::read_to_string(&creds_path)
+            .map_err(|e| format!("Failed to read credentials.json: {}", e))?;
+
+        let mut secret: ServiceAccountKey = serde_json::from_str(&secret_json)
+            .map_err(|e| format!("Failed to parse service account key: {}", e))?;
+        if let Some(token_uri) = &config.drive_api.token_uri {
+            secret.token_uri = token_uri.clone();
+        }
+        let secret_email = secret.client_email.clone();
+
+        // Token requests go through the same proxy and CA roots as API calls
+        let connector = network::connector(&config.network)?;
+        let auth_client = hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+            .build::<_, String>(connector.clone());
+        let auth = google_drive3::yup_oauth2::ServiceAccountAuthenticator::with_client(secret, auth_client)
+            .build()
+            .await
+            .map_err(|e| format!("Failed to create authenticator: {}", e))?;
+        // Fetch a token now so wrong credentials or an unreachable token server show up at startup
+        auth.token(&[DRIVE_SCOPE])
+            .await
+            .map_err(|e| format!("Failed to obtain an access token for {}: {}", secret_email, e))?;
+
+        let client = hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+            .build(connector);
+
+        let mut hub = DriveHub::new(client, auth);
+        if let Some(base_url) = &config.drive_api.base_url {
+            hub.base_url(base_url.clone());
+        }
+        // Upload requests are built from the root URL rather than the base URL
+        if let Some(upload_url) = &config.drive_api.upload_url {
+            hub.root_url(upload_url.clone());
+        }
+
+        log_info("Google Drive connection established successfully");
+        Ok(DriveBackend { hub, folder_id: folder_id.to_string() })
+    }
+}
+
+#[async_trait]
+impl StorageBackend for DriveBackend {
+    fn describe(&self) -> String {
+        format!("Google Drive folder {}", self.folder_id)
+    }
+
+    fn root_folder(&self) -> &str {
+        &self.folder_id
+    }
+
+    fn allows_duplicate_names(&self) -> bool {
+        true
+    }
+
+    async fn create(&self, folder_id: &str, source: &Path, metadata: &UploadMetadata) -> Result<RemoteFile, String> {
+        let file_content = fs::File::open(source)
+            .map_err(|e| format!("Failed to open file {:?}: {}", source, e))?;
+
+        let mime_type = match "application/octet-stream".parse() {
+            Ok(mt) => mt,
+            Err(e) => return Err(format!("Failed to parse MIME type: {}", e)),
+        };
+
+        let mut remote_file = drive_file(metadata);
+        remote_file.parents = Some(vec![folder_id.to_string()]);
+        remote_file.created_time = metadata.created_time;
+
+        self.hub
+            .files()
+            .create(remote_file)
+            .param("fields", FILE_FIELDS)
+            .upload(file_content, mime_type)
+            .await
+            .map(|(_, file)| remote_file_from(file))
+            .map_err(|e| e.to_string())
+    }
+
+    // Uploads the content as a new revision of the existing file
+    async fn update(&self, file_id: &str, source: &Path, metadata: &UploadMetadata) -> Result<RemoteFile, String> {
+        let file_content = fs::File::open(source)
+            .map_err(|e| format!("Failed to open file {:?}: {}", source, e))?;
+
+        let mime_type = match "application/octet-stream".parse() {
+            Ok(mt) => mt,
+            Err(e) => return Err(format!("Failed to parse MIME type: {}", e)),
+        };
+
+        // Parents and createdTime can only be set when a file is created
+        self.hub
+            .files()
+            .update(drive_file(metadata), file_id)
+            .param("fields", FILE_FIELDS)
+            .upload(file_content, mime_type)
+            .await
+            .map(|(_, file)| remote_file_from(file))
+            .map_err(|e| e.to_string())
+    }
+
+    // Non-trashed files directly in the folder
+    async fn list(&self, folder_id: &str, name: Option<&str>) -> Result<Vec<RemoteFile>, String> {
+        let mut query = format!("'{}' in parents and trashed = false", escape_query_value(folder_id));
+        if let Some(name) = name {
+            query = format!("name = '{}' and {}", escape_query_value(name), query);
+        }
+
+        let mut files = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut request = self
+                .hub
+                .files()
+                .list()
+                .q(&query)
+                .param("fields", &format!("nextPageToken,files({})", FILE_FIELDS))
+                .page_size(1000);
+            if let Some(token) = &page_token {
+                request = request.page_token(token);
+            }
+            let (_, list) = request
+                .doit()
+                .await
+                .map_err(|e| format!("Failed to list Google Drive folder {}: {}", folder_id, e))?;
+
+            files.extend(list.files.unwrap_or_default().into_iter().map(remote_file_from));
+            page_token = list.next_page_token;
+            if page_token.is_none() {
+                return Ok(files);
+            }
+        }
+    }
+
+    async fn mkdir(&self, parent_id: &str, name: &str) -> Result<String, String> {
+        let folder = api::File {
+            name: Some(name.to_string()),
+            mime_type: Some(FOLDER_MIME_TYPE.to_string()),
+            parents: Some(vec![parent_id.to_string()]),
+            ..Default::default()
+        };
+        let mime_type = match FOLDER_MIME_TYPE.parse() {
+            Ok(mt) => mt,
+            Err(e) => return Err(format!("Failed to parse MIME type: {}", e)),
+        };
+        // FileCreateCall has no metadata-only variant; a folder is an empty upload
+        let (_, created) = self
+            .hub
+            .files()
+            .create(folder)
+            .param("fields", "id")
+            .upload(std::io::Cursor::new(Vec::new()), mime_type)
+            .await
+            .map_err(|e| format!("Failed to create folder '{}' in {}: {}", name, parent_id, e))?;
+        created.id.ok_or_else(|| format!("Google Drive returned no ID for folder '{}'", name))
+    }
+
+    async fn trash(&self, file_id: &str) -> Result<(), String> {
+        let update = api::File {
+            trashed: Some(true),
+            ..Default::default()
+        };
+        self.hub
+            .files()
+            .update(update, file_id)
+            .doit_without_upload()
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("Failed to move file {} to trash: {}", file_id, e))
+    }
+
+    async fn metadata(&self, file_id: &str) -> Result<RemoteFile, String> {
+        self.hub
+            .files()
+            .get(file_id)
+            .param("fields", FILE_FIELDS)
+            .doit()
+            .await
+            .map(|(_, file)| remote_file_from(file))
+            .map_err(|e| format!("Failed to get metadata of file {}: {}", file_id, e))
+    }
+
+    async fn download(&self, file_id: &str, destination: &Path) -> Result<(), String> {
+        let (response, _) = self
+            .hub
+            .files()
+            .get(file_id)
+            .param("alt", "media")
+            .doit()
+            .await
+            .map_err(|e| format!("Failed to download file {}: {}", file_id, e))?;
+        let bytes = google_drive3::common::to_bytes(response.into_body())
+            .await
+            .ok_or_else(|| format!("Failed to read download of file {}", file_id))?;
+        fs::write(destination, &bytes).map_err(|e| format!("Failed to write {:?}: {}", destination, e))
+    }
+
+    async fn quota(&self) -> Result<Option<StorageQuota>, String> {
+        let (_, about) = self
+            .hub
+            .about()
+            .get()
+            .param("fields", "storageQuota")
+            .doit()
+            .await
+            .map_err(|e| format!("Failed to check Google Drive storage quota: {}", e))?;
+        // No limit is reported for unlimited storage
+        Ok(about
+            .storage_quota
+            .and_then(|q| Some(StorageQuota { usage: q.usage?, limit: q.limit? }))
+            .filter(|q| q.limit > 0))
+    }
+
+    // Read-only: Drive reports whether the service account may add files
+    async fn check_access(&self) -> Result<(), String> {
+        let (_, folder) = self
+            .hub
+            .files()
+            .get(&self.folder_id)
+            .param("fields", "id,name,mimeType,trashed,capabilities/canAddChildren")
+            .doit()
+            .await
+            .map_err(|e| format!(
+                "Google Drive folder {} is not accessible (is it shared with the service account?): {}",
+                self.folder_id, e
+            ))?;
+
+        let name = folder.name.unwrap_or_default();
+        if folder.mime_type.as_deref() != Some(FOLDER_MIME_TYPE) {
+            return Err(format!("gdrive_folder_id {} ('{}') is not a folder", self.folder_id, name));
+        }
+        if folder.trashed == Some(true) {
+            return Err(format!("Google Drive folder '{}' is in the trash", name));
+        }
+        if !folder.capabilities.and_then(|c| c.can_add_children).unwrap_or(false) {
+            return Err(format!(
+                "The service account cannot add files to Google Drive folder '{}'; share it with Editor access",
+                name
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Name, local timestamps and provenance appProperties; parents and createdTime
+// are added by `create`, since Drive rejects them on update
+fn drive_file(metadata: &UploadMetadata) -> api::File {
+    api::File {
+        name: Some(metadata.name.clone()),
+        modified_time: metadata.modified_time,
+        app_properties: (!metadata.properties.is_empty()).then(|| metadata.properties.clone()),
+        ..Default::default()
+    }
+}
+
+fn remote_file_from(file: api::File) -> RemoteFile {
+    RemoteFile {
+        id: file.id.unwrap_or_default(),
+        name: file.name.unwrap_or_default(),
+        size: file.size.map(|s| s as u64),
+        md5: file.md5_checksum,
+    }
+}
+
+// Drive query strings are single-quoted; quotes and backslashes must be escaped
+fn escape_query_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\'', "\\'")
+}
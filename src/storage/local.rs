@@ -0,0 +1,167 @@
+use async_trait::async_trait;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{RemoteFile, StorageBackend, StorageQuota, UploadMetadata};
+use crate::checksum;
+use crate::config;
+
+// Trashed files are moved here, below the destination root
+const TRASH_DIR: &str = ".trash";
+// Suffix of copies in progress; renamed into place once complete
+const PARTIAL_SUFFIX: &str = ".partial";
+
+// A local or mounted directory (USB disk, NAS share). IDs are paths relative to
+// the destination root with '/' separators; the root itself is "".
+pub struct LocalBackend {
+    root: PathBuf,
+}
+
+impl LocalBackend {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let root = config::resolve_path(path)?;
+        fs::create_dir_all(&root)
+            .map_err(|e| format!("Failed to create destination folder {:?}: {}", root, e))?;
+        Ok(LocalBackend { root })
+    }
+
+    fn path_of(&self, id: &str) -> PathBuf {
+        id.split('/').filter(|part| !part.is_empty()).fold(self.root.clone(), |acc, part| acc.join(part))
+    }
+
+    // Copies next to the target and renames, so readers never see a partial file
+    fn copy_into_place(&self, source: &Path, target: &Path, metadata: &UploadMetadata) -> Result<(), String> {
+        let file_name = target.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        let partial = target.with_file_name(format!(".{}{}", file_name, PARTIAL_SUFFIX));
+        fs::copy(source, &partial).map_err(|e| format!("Failed to copy {:?} to {:?}: {}", source, partial, e))?;
+
+        if let Some(modified) = metadata.modified_time {
+            let result = fs::File::options()
+                .write(true)
+                .open(&partial)
+                .and_then(|f| f.set_modified(modified.into()));
+            if let Err(e) = result {
+                log::error!("Failed to set modification time of {:?}: {}", partial, e);
+            }
+        }
+
+        fs::rename(&partial, target).map_err(|e| {
+            let _ = fs::remove_file(&partial);
+            format!("Failed to move {:?} into place: {}", target, e)
+        })
+    }
+
+    fn describe_file(&self, id: &str, with_md5: bool) -> Result<RemoteFile, String> {
+        let path = self.path_of(id);
+        let metadata = fs::metadata(&path).map_err(|e| format!("Failed to read metadata of {:?}: {}", path, e))?;
+        let md5 = if with_md5 {
+            Some(checksum::md5_file(&path).map_err(|e| format!("Failed to hash {:?}: {}", path, e))?)
+        } else {
+            None
+        };
+        Ok(RemoteFile {
+            id: id.to_string(),
+            name: id.rsplit('/').next().unwrap_or(id).to_string(),
+            size: Some(metadata.len()),
+            md5,
+        })
+    }
+}
+
+fn child_id(folder_id: &str, name: &str) -> String {
+    if folder_id.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", folder_id, name)
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalBackend {
+    fn describe(&self) -> String {
+        format!("local folder {}", self.root.display())
+    }
+
+    fn root_folder(&self) -> &str {
+        ""
+    }
+
+    async fn create(&self, folder_id: &str, source: &Path, metadata: &UploadMetadata) -> Result<RemoteFile, String> {
+        let id = child_id(folder_id, &metadata.name);
+        let target = self.path_of(&id);
+        if target.exists() {
+            return Err(format!("{:?} already exists", target));
+        }
+        self.copy_into_place(source, &target, metadata)?;
+        // Hash what actually landed on the destination, so verification means something
+        self.describe_file(&id, true)
+    }
+
+    async fn update(&self, file_id: &str, source: &Path, metadata: &UploadMetadata) -> Result<RemoteFile, String> {
+        let target = self.path_of(file_id);
+        if !target.is_file() {
+            return Err(format!("{:?} does not exist", target));
+        }
+        self.copy_into_place(source, &target, metadata)?;
+        self.describe_file(file_id, true)
+    }
+
+    async fn list(&self, folder_id: &str, name: Option<&str>) -> Result<Vec<RemoteFile>, String> {
+        let dir = self.path_of(folder_id);
+        let entries = fs::read_dir(&dir).map_err(|e| format!("Failed to list {:?}: {}", dir, e))?;
+
+        let mut files = Vec::new();
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let hidden = file_name == TRASH_DIR || file_name.ends_with(PARTIAL_SUFFIX);
+            let is_file = entry.file_type().map(|t| t.is_file()).unwrap_or(false);
+            if hidden || !is_file || name.is_some_and(|n| n != file_name) {
+                continue;
+            }
+            files.push(self.describe_file(&child_id(folder_id, &file_name), false)?);
+        }
+        Ok(files)
+    }
+
+    async fn mkdir(&self, parent_id: &str, name: &str) -> Result<String, String> {
+        let id = child_id(parent_id, name);
+        let path = self.path_of(&id);
+        fs::create_dir_all(&path).map_err(|e| format!("Failed to create folder {:?}: {}", path, e))?;
+        Ok(id)
+    }
+
+    // Keeps the file under .trash with a timestamp, like Drive's trash
+    async fn trash(&self, file_id: &str) -> Result<(), String> {
+        let path = self.path_of(file_id);
+        let trash_dir = self.root.join(TRASH_DIR);
+        fs::create_dir_all(&trash_dir).map_err(|e| format!("Failed to create {:?}: {}", trash_dir, e))?;
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
+        let destination = trash_dir.join(format!("{}_{}", chrono::Local::now().format("%Y%m%d_%H%M%S"), name));
+        fs::rename(&path, &destination).map_err(|e| format!("Failed to move {:?} to trash: {}", path, e))
+    }
+
+    async fn metadata(&self, file_id: &str) -> Result<RemoteFile, String> {
+        self.describe_file(file_id, true)
+    }
+
+    async fn download(&self, file_id: &str, destination: &Path) -> Result<(), String> {
+        let path = self.path_of(file_id);
+        fs::copy(&path, destination)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to copy {:?} to {:?}: {}", path, destination, e))
+    }
+
+    async fn quota(&self) -> Result<Option<StorageQuota>, String> {
+        Ok(None)
+    }
+
+    // Checked directly, so the probe does not end up in .trash
+    async fn check_access(&self) -> Result<(), String> {
+        if !self.root.is_dir() {
+            return Err(format!("{:?} is not a folder", self.root));
+        }
+        let probe = self.root.join(format!(".probe{}", PARTIAL_SUFFIX));
+        fs::write(&probe, b"probe").map_err(|e| format!("{:?} is not writable: {}", self.root, e))?;
+        fs::remove_file(&probe).map_err(|e| format!("Failed to remove {:?}: {}", probe, e))
+    }
+}
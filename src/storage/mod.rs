@@ -0,0 +1,167 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full, StreamBody};
+use hyper::body::Frame;
+use hyper::body::Bytes;
+use hyper_util::client::legacy::Client;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::config::{Config, DestinationConfig, NetworkConfig};
+use crate::network;
+
+mod drive;
+mod local;
+mod s3;
+mod sftp;
+mod webdav;
+
+pub use drive::DriveBackend;
+pub use local::LocalBackend;
+pub use s3::S3Backend;
+pub use sftp::SftpBackend;
+pub use webdav::WebdavBackend;
+
+// What the sync engine needs from an upload destination. Files and folders are
+// addressed by IDs chosen by the backend (Drive file IDs, paths relative to the
+// destination root, ...); `root_folder` is the ID uploads go into.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    // Where uploads go, for logs and the audit trail
+    fn describe(&self) -> String;
+
+    fn root_folder(&self) -> &str;
+
+    // Drive can hold several files with the same name in one folder; most
+    // destinations cannot, so the "duplicate" conflict policy renames there
+    fn allows_duplicate_names(&self) -> bool {
+        false
+    }
+
+    async fn create(&self, folder_id: &str, source: &Path, metadata: &UploadMetadata) -> Result<RemoteFile, String>;
+
+    // Replaces the content of an existing file (a new revision where supported)
+    async fn update(&self, file_id: &str, source: &Path, metadata: &UploadMetadata) -> Result<RemoteFile, String>;
+
+    // Files directly in the folder, optionally only those with exactly this name
+    async fn list(&self, folder_id: &str, name: Option<&str>) -> Result<Vec<RemoteFile>, String>;
+
+    // Returns the ID of the new folder; uploads all go into the root folder for
+    // now, so the engine does not call this yet
+    #[allow(dead_code)]
+    async fn mkdir(&self, parent_id: &str, name: &str) -> Result<String, String>;
+
+    async fn trash(&self, file_id: &str) -> Result<(), String>;
+
+    async fn metadata(&self, file_id: &str) -> Result<RemoteFile, String>;
+
+    async fn download(&self, file_id: &str, destination: &Path) -> Result<(), String>;
+
+    // Storage use, or None when the destination reports no limit
+    async fn quota(&self) -> Result<Option<StorageQuota>, String>;
+
+    // Startup check that the root folder exists and accepts uploads: writes a
+    // small probe file and removes it again
+    async fn check_access(&self) -> Result<(), String> {
+        let probe_path = std::env::temp_dir().join(format!("gdrive-stealth-sync-probe-{}", std::process::id()));
+        std::fs::write(&probe_path, b"probe").map_err(|e| format!("Failed to write {:?}: {}", probe_path, e))?;
+        let metadata = UploadMetadata {
+            name: PROBE_NAME.to_string(),
+            ..UploadMetadata::default()
+        };
+        let result = self.create(self.root_folder(), &probe_path, &metadata).await;
+        let _ = std::fs::remove_file(&probe_path);
+
+        let probe = result.map_err(|e| format!("{} is not writable: {}", self.describe(), e))?;
+        self.trash(&probe.id)
+            .await
+            .map_err(|e| format!("Failed to remove probe file from {}: {}", self.describe(), e))
+    }
+}
+
+// Name of the file written by the default access check
+const PROBE_NAME: &str = ".gdrive-stealth-sync-probe";
+
+// Metadata for a file being uploaded
+#[derive(Clone, Default)]
+pub struct UploadMetadata {
+    pub name: String,
+    pub modified_time: Option<DateTime<Utc>>,
+    pub created_time: Option<DateTime<Utc>>,
+    // Provenance key/value pairs, stored where the destination supports them
+    pub properties: HashMap<String, String>,
+}
+
+// A file as reported by the destination after an upload or lookup
+pub struct RemoteFile {
+    pub id: String,
+    pub name: String,
+    pub size: Option<u64>,
+    // Used to verify uploads; None when the destination does not report it
+    pub md5: Option<String>,
+}
+
+pub struct StorageQuota {
+    pub usage: i64,
+    pub limit: i64,
+}
+
+// Sets up the destination configured for the mapping
+pub async fn connect(base_dir: &Path, config: &Config) -> Result<Box<dyn StorageBackend>, String> {
+    match &config.destination {
+        DestinationConfig::Drive => {
+            let backend = DriveBackend::connect(base_dir, config).await?;
+            Ok(Box::new(backend))
+        }
+        DestinationConfig::Local { path } => Ok(Box::new(LocalBackend::open(path)?)),
+        DestinationConfig::S3(settings) => Ok(Box::new(S3Backend::open(settings, &config.network)?)),
+        DestinationConfig::Webdav(settings) => Ok(Box::new(WebdavBackend::connect(settings, &config.network).await?)),
+        DestinationConfig::Sftp(settings) => Ok(Box::new(SftpBackend::connect(settings).await?)),
+    }
+}
+
+// Client for the backends that talk HTTP directly (S3, WebDAV)
+type HttpClient = Client<network::Connector, HttpBody>;
+type HttpBody = BoxBody<Bytes, std::io::Error>;
+
+fn https_client(network: &NetworkConfig) -> Result<HttpClient, String> {
+    Ok(Client::builder(hyper_util::rt::TokioExecutor::new()).build(network::connector(network)?))
+}
+
+fn full_body(bytes: Bytes) -> HttpBody {
+    Full::new(bytes).map_err(|never| match never {}).boxed()
+}
+
+// Streams the file from disk instead of holding all of it in memory
+async fn file_body(path: &Path) -> Result<HttpBody, String> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| format!("Failed to open file {:?}: {}", path, e))?;
+    let frames = tokio_util::io::ReaderStream::new(file).map_ok(Frame::data);
+    Ok(StreamBody::new(frames).boxed())
+}
+
+// Percent-encodes everything but unreserved characters, and '/' too unless it
+// separates path segments
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn xml_unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
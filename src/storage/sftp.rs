@@ -0,0 +1,436 @@
+use async_trait::async_trait;
+use ssh2::{CheckResult, ErrorCode, FileStat, KnownHostFileKind, RenameFlags, Session, Sftp};
+use std::fs;
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use super::{RemoteFile, StorageBackend, StorageQuota, UploadMetadata};
+use crate::config::{self, SftpConfig};
+use crate::log_info;
+
+// Suffix of uploads in progress; renamed into place once complete
+const PARTIAL_SUFFIX: &str = ".partial";
+const SESSION_TIMEOUT_MS: u32 = 60_000;
+// Per resolved address, so an unreachable host does not hold the session lock for minutes
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+// LIBSSH2_FX_NO_SUCH_FILE
+const SFTP_NO_SUCH_FILE: i32 = 2;
+
+// A directory on an SFTP server. IDs are absolute remote paths with '/'
+// separators; the configured remote_dir is the root. libssh2 is blocking, so
+// every operation runs on the blocking thread pool over one shared session.
+pub struct SftpBackend {
+    inner: Arc<SftpInner>,
+    root: String,
+}
+
+struct SftpInner {
+    settings: SftpConfig,
+    private_key: PathBuf,
+    known_hosts: PathBuf,
+    // Dropped after a transport or session error and re-established by the
+    // next operation
+    connection: Mutex<Option<Connection>>,
+}
+
+// Why an operation failed. SFTP status errors (no such file, permission
+// denied, ...) leave the connection usable; SSH session errors do not.
+struct Failure {
+    message: String,
+    connection_lost: bool,
+}
+
+impl Failure {
+    fn ssh(error: &ssh2::Error, message: String) -> Self {
+        Failure {
+            message,
+            connection_lost: matches!(error.code(), ErrorCode::Session(_)),
+        }
+    }
+
+    // ssh2 turns errors while streaming a file into io::Error without their
+    // code, so an interrupted transfer counts as a lost connection
+    fn transfer(message: String) -> Self {
+        Failure { message, connection_lost: true }
+    }
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Failure { message, connection_lost: false }
+    }
+}
+
+struct Connection {
+    // Kept alive for as long as the SFTP channel is in use
+    _session: Session,
+    sftp: Sftp,
+}
+
+impl SftpBackend {
+    pub async fn connect(settings: &SftpConfig) -> Result<Self, String> {
+        let root = settings.remote_dir.trim_end_matches('/').to_string();
+        if root.is_empty() {
+            return Err("destination.remote_dir is not set in config.json".to_string());
+        }
+
+        let backend = SftpBackend {
+            inner: Arc::new(SftpInner {
+                settings: settings.clone(),
+                private_key: config::resolve_path(&settings.private_key)?,
+                known_hosts: config::resolve_path(&settings.known_hosts)?,
+                connection: Mutex::new(None),
+            }),
+            root: root.clone(),
+        };
+
+        // Connect now so bad credentials or host keys show up at startup
+        backend.run(move |sftp| create_dirs(sftp, &root)).await?;
+        log_info(&format!("Connected to SFTP server {}:{}", settings.host, settings.port));
+        Ok(backend)
+    }
+
+    async fn run<T, F>(&self, operation: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Sftp) -> Result<T, Failure> + Send + 'static,
+    {
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || inner.with_sftp(operation))
+            .await
+            .map_err(|e| format!("SFTP operation panicked: {}", e))?
+    }
+}
+
+impl SftpInner {
+    fn with_sftp<T>(&self, operation: impl FnOnce(&Sftp) -> Result<T, Failure>) -> Result<T, String> {
+        let mut connection = self.connection.lock().unwrap_or_else(|e| e.into_inner());
+        if connection.is_none() {
+            *connection = Some(self.open()?);
+        }
+        operation(&connection.as_ref().expect("connection was just opened").sftp).map_err(|failure| {
+            if failure.connection_lost {
+                *connection = None;
+            }
+            failure.message
+        })
+    }
+
+    fn open(&self) -> Result<Connection, String> {
+        let settings = &self.settings;
+        let address = format!("{}:{}", settings.host, settings.port);
+        let tcp = connect_tcp(&address)?;
+
+        let mut session = Session::new().map_err(|e| format!("Failed to create SSH session: {}", e))?;
+        session.set_timeout(SESSION_TIMEOUT_MS);
+        session.set_tcp_stream(tcp);
+        session
+            .handshake()
+            .map_err(|e| format!("SSH handshake with {} failed: {}", address, e))?;
+        self.verify_host_key(&session)?;
+
+        session
+            .userauth_pubkey_file(&settings.username, None, &self.private_key, settings.passphrase.as_deref())
+            .map_err(|e| format!("SSH key authentication as '{}' on {} failed: {}", settings.username, address, e))?;
+        let sftp = session
+            .sftp()
+            .map_err(|e| format!("Failed to start SFTP on {}: {}", address, e))?;
+        Ok(Connection { _session: session, sftp })
+    }
+
+    // Refuses servers whose host key is not already in known_hosts, so a
+    // man-in-the-middle cannot receive the files
+    fn verify_host_key(&self, session: &Session) -> Result<(), String> {
+        let settings = &self.settings;
+        let mut known_hosts = session
+            .known_hosts()
+            .map_err(|e| format!("Failed to initialise known hosts: {}", e))?;
+        known_hosts
+            .read_file(&self.known_hosts, KnownHostFileKind::OpenSSH)
+            .map_err(|e| format!("Failed to read known_hosts file {:?}: {}", self.known_hosts, e))?;
+        let (key, _) = session
+            .host_key()
+            .ok_or_else(|| format!("{} sent no host key", settings.host))?;
+
+        match known_hosts.check_port(&settings.host, settings.port, key) {
+            CheckResult::Match => Ok(()),
+            CheckResult::NotFound => Err(format!(
+                "Host key of {}:{} is not in {:?}; add it with: ssh-keyscan -p {} {} >> known_hosts",
+                settings.host, settings.port, self.known_hosts, settings.port, settings.host
+            )),
+            CheckResult::Mismatch => Err(format!(
+                "Host key of {}:{} does not match {:?}; refusing to connect",
+                settings.host, settings.port, self.known_hosts
+            )),
+            CheckResult::Failure => Err(format!("Failed to check the host key of {}", settings.host)),
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for SftpBackend {
+    fn describe(&self) -> String {
+        let settings = &self.inner.settings;
+        format!("sftp://{}@{}:{}{}", settings.username, settings.host, settings.port, self.root)
+    }
+
+    fn root_folder(&self) -> &str {
+        &self.root
+    }
+
+    async fn create(&self, folder_id: &str, source: &Path, metadata: &UploadMetadata) -> Result<RemoteFile, String> {
+        let folder = folder_id.to_string();
+        let name = metadata.name.clone();
+        let source = source.to_path_buf();
+        let modified = metadata.modified_time.map(|t| t.timestamp().max(0) as u64);
+        self.run(move |sftp| {
+            create_dirs(sftp, &folder)?;
+            let target = child_id(&folder, &name);
+            if stat(sftp, &target)?.is_some() {
+                return Err(format!("{} already exists", target).into());
+            }
+            upload_into_place(sftp, &source, &target, modified, false)?;
+            describe_file(sftp, &target)
+        })
+        .await
+    }
+
+    async fn update(&self, file_id: &str, source: &Path, metadata: &UploadMetadata) -> Result<RemoteFile, String> {
+        let target = file_id.to_string();
+        let source = source.to_path_buf();
+        let modified = metadata.modified_time.map(|t| t.timestamp().max(0) as u64);
+        self.run(move |sftp| {
+            upload_into_place(sftp, &source, &target, modified, true)?;
+            describe_file(sftp, &target)
+        })
+        .await
+    }
+
+    async fn list(&self, folder_id: &str, name: Option<&str>) -> Result<Vec<RemoteFile>, String> {
+        let folder = folder_id.to_string();
+        let name = name.map(str::to_string);
+        self.run(move |sftp| {
+            if let Some(name) = name {
+                let id = child_id(&folder, &name);
+                return Ok(match stat(sftp, &id)? {
+                    Some(stat) if stat.is_file() => vec![remote_file(&id, &stat)],
+                    _ => Vec::new(),
+                });
+            }
+
+            let entries = sftp
+                .readdir(Path::new(&folder))
+                .map_err(|e| Failure::ssh(&e, format!("Failed to list {}: {}", folder, e)))?;
+            Ok(entries
+                .iter()
+                .filter(|(path, stat)| stat.is_file() && !path.to_string_lossy().ends_with(PARTIAL_SUFFIX))
+                .map(|(path, stat)| {
+                    let name = path.to_string_lossy().rsplit('/').next().unwrap_or_default().to_string();
+                    remote_file(&child_id(&folder, &name), stat)
+                })
+                .collect())
+        })
+        .await
+    }
+
+    async fn mkdir(&self, parent_id: &str, name: &str) -> Result<String, String> {
+        let id = child_id(parent_id, name);
+        let path = id.clone();
+        self.run(move |sftp| create_dirs(sftp, &path)).await?;
+        Ok(id)
+    }
+
+    // SFTP has no trash; the file is removed
+    async fn trash(&self, file_id: &str) -> Result<(), String> {
+        let path = file_id.to_string();
+        self.run(move |sftp| {
+            sftp.unlink(Path::new(&path))
+                .map_err(|e| Failure::ssh(&e, format!("Failed to delete {}: {}", path, e)))
+        })
+        .await
+    }
+
+    async fn metadata(&self, file_id: &str) -> Result<RemoteFile, String> {
+        let path = file_id.to_string();
+        self.run(move |sftp| describe_file(sftp, &path)).await
+    }
+
+    async fn download(&self, file_id: &str, destination: &Path) -> Result<(), String> {
+        let path = file_id.to_string();
+        let destination = destination.to_path_buf();
+        self.run(move |sftp| {
+            let mut remote = sftp
+                .open(Path::new(&path))
+                .map_err(|e| Failure::ssh(&e, format!("Failed to open {}: {}", path, e)))?;
+            let mut local = fs::File::create(&destination)
+                .map_err(|e| format!("Failed to create {:?}: {}", destination, e))?;
+            io::copy(&mut remote, &mut local)
+                .map(|_| ())
+                .map_err(|e| Failure::transfer(format!("Failed to download {}: {}", path, e)))
+        })
+        .await
+    }
+
+    async fn quota(&self) -> Result<Option<StorageQuota>, String> {
+        Ok(None)
+    }
+}
+
+// Uploads under a temporary name next to the target and renames it into place,
+// so receivers polling the directory never pick up a partial file
+fn upload_into_place(sftp: &Sftp, source: &Path, target: &str, modified: Option<u64>, overwrite: bool) -> Result<(), Failure> {
+    let (folder, name) = target.rsplit_once('/').unwrap_or(("", target));
+    let partial = format!("{}/.{}{}", folder, name, PARTIAL_SUFFIX);
+
+    let mut local = fs::File::open(source).map_err(|e| format!("Failed to open file {:?}: {}", source, e))?;
+    let result = sftp
+        .create(Path::new(&partial))
+        .map_err(|e| Failure::ssh(&e, format!("Failed to create {}: {}", partial, e)))
+        .and_then(|mut remote| {
+            io::copy(&mut local, &mut remote).map_err(|e| Failure::transfer(format!("Failed to upload to {}: {}", partial, e)))
+        })
+        .and_then(|_| match modified {
+            Some(mtime) => {
+                let times = FileStat { size: None, uid: None, gid: None, perm: None, atime: Some(mtime), mtime: Some(mtime) };
+                sftp.setstat(Path::new(&partial), times)
+                    .map_err(|e| Failure::ssh(&e, format!("Failed to set modification time of {}: {}", partial, e)))
+            }
+            None => Ok(()),
+        })
+        .and_then(|_| rename_into_place(sftp, &partial, target, overwrite));
+
+    if result.is_err() {
+        let _ = sftp.unlink(Path::new(&partial));
+    }
+    result
+}
+
+fn rename_into_place(sftp: &Sftp, partial: &str, target: &str, overwrite: bool) -> Result<(), Failure> {
+    let flags = if overwrite {
+        RenameFlags::ATOMIC | RenameFlags::NATIVE | RenameFlags::OVERWRITE
+    } else {
+        RenameFlags::ATOMIC | RenameFlags::NATIVE
+    };
+    let error = match sftp.rename(Path::new(partial), Path::new(target), Some(flags)) {
+        Ok(()) => return Ok(()),
+        Err(e) => e,
+    };
+    if !overwrite || stat(sftp, target)?.is_none() {
+        return Err(Failure::ssh(&error, format!("Failed to rename {} to {}: {}", partial, target, error)));
+    }
+
+    // SFTP v3 servers refuse to rename over an existing file: move the old copy
+    // aside first, so the target is briefly missing but never partial
+    let previous = format!("{}.previous", partial);
+    sftp.rename(Path::new(target), Path::new(&previous), None)
+        .map_err(|e| Failure::ssh(&e, format!("Failed to move {} aside: {}", target, e)))?;
+    if let Err(e) = sftp.rename(Path::new(partial), Path::new(target), None) {
+        let _ = sftp.rename(Path::new(&previous), Path::new(target), None);
+        return Err(Failure::ssh(&e, format!("Failed to rename {} to {}: {}", partial, target, e)));
+    }
+    if let Err(e) = sftp.unlink(Path::new(&previous)) {
+        log::error!("Failed to remove previous copy {}: {}", previous, e);
+    }
+    Ok(())
+}
+
+// mkdir -p: creates each missing directory along the path
+fn create_dirs(sftp: &Sftp, path: &str) -> Result<(), Failure> {
+    let mut current = String::new();
+    for part in path.split('/').filter(|part| !part.is_empty()) {
+        if current.is_empty() && !path.starts_with('/') {
+            current.push_str(part);
+        } else {
+            current = format!("{}/{}", current, part);
+        }
+        match stat(sftp, &current)? {
+            Some(stat) if stat.is_dir() => {}
+            Some(_) => return Err(format!("{} exists but is not a directory", current).into()),
+            None => sftp
+                .mkdir(Path::new(&current), 0o755)
+                .map_err(|e| Failure::ssh(&e, format!("Failed to create directory {}: {}", current, e)))?,
+        }
+    }
+    Ok(())
+}
+
+// None when the path does not exist
+fn stat(sftp: &Sftp, path: &str) -> Result<Option<FileStat>, Failure> {
+    match sftp.stat(Path::new(path)) {
+        Ok(stat) => Ok(Some(stat)),
+        Err(e) if e.code() == ErrorCode::SFTP(SFTP_NO_SUCH_FILE) => Ok(None),
+        Err(e) => Err(Failure::ssh(&e, format!("Failed to stat {}: {}", path, e))),
+    }
+}
+
+// SFTP reports no checksums, so uploads are verified by size
+fn describe_file(sftp: &Sftp, path: &str) -> Result<RemoteFile, Failure> {
+    match stat(sftp, path)? {
+        Some(stat) => Ok(remote_file(path, &stat)),
+        None => Err(format!("{} does not exist", path).into()),
+    }
+}
+
+fn remote_file(id: &str, stat: &FileStat) -> RemoteFile {
+    RemoteFile {
+        id: id.to_string(),
+        name: id.rsplit('/').next().unwrap_or(id).to_string(),
+        size: stat.size,
+        md5: None,
+    }
+}
+
+fn child_id(folder_id: &str, name: &str) -> String {
+    format!("{}/{}", folder_id.trim_end_matches('/'), name.replace('\\', "/"))
+}
+
+// Tries each address the host resolves to, like TcpStream::connect, but with a timeout
+fn connect_tcp(address: &str) -> Result<TcpStream, String> {
+    let addresses = address
+        .to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve {}: {}", address, e))?;
+    let mut last_error = format!("{} did not resolve to any address", address);
+    for socket_address in addresses {
+        match TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT) {
+            Ok(tcp) => return Ok(tcp),
+            Err(e) => last_error = format!("Failed to connect to {} ({}): {}", address, socket_address, e),
+        }
+    }
+    Err(last_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connects_to_the_first_reachable_address() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(connect_tcp(&format!("localhost:{}", port)).is_ok());
+
+        drop(listener);
+        let error = connect_tcp(&format!("127.0.0.1:{}", port)).unwrap_err();
+        assert!(error.starts_with(&format!("Failed to connect to 127.0.0.1:{}", port)), "{}", error);
+        assert!(connect_tcp("host.invalid:22").unwrap_err().starts_with("Failed to resolve"));
+    }
+
+    #[test]
+    fn only_session_errors_drop_the_connection() {
+        // LIBSSH2_FX_PERMISSION_DENIED, LIBSSH2_FX_FAILURE
+        for status in [SFTP_NO_SUCH_FILE, 3, 4] {
+            let error = ssh2::Error::new(ErrorCode::SFTP(status), "status");
+            assert!(!Failure::ssh(&error, String::new()).connection_lost, "{}", status);
+        }
+        // LIBSSH2_ERROR_SOCKET_SEND, LIBSSH2_ERROR_TIMEOUT, LIBSSH2_ERROR_SOCKET_DISCONNECT
+        for code in [-7, -9, -13] {
+            let error = ssh2::Error::new(ErrorCode::Session(code), "session");
+            assert!(Failure::ssh(&error, String::new()).connection_lost, "{}", code);
+        }
+        assert!(!Failure::from("local file missing".to_string()).connection_lost);
+        assert!(Failure::transfer("interrupted".to_string()).connection_lost);
+    }
+}
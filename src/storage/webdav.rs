@@ -0,0 +1,603 @@
+use async_trait::async_trait;
+use base64::Engine;
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use hyper::{HeaderMap, Method, StatusCode};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use super::{file_body, full_body, https_client, uri_encode, xml_unescape, HttpBody, HttpClient, RemoteFile, StorageBackend, StorageQuota, UploadMetadata};
+use crate::config::{NetworkConfig, WebdavConfig};
+use crate::log_info;
+
+// Nextcloud numbers chunks 1..=10000 and needs at least 5 MiB per chunk but the last
+const MIN_CHUNK_SIZE: u64 = 5 * 1024 * 1024;
+const MAX_CHUNKS: u64 = 10_000;
+
+const PROPFIND_FILE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:resourcetype/><d:getcontentlength/></d:prop></d:propfind>"#;
+const PROPFIND_QUOTA: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:quota-used-bytes/><d:quota-available-bytes/></d:prop></d:propfind>"#;
+
+// A WebDAV collection (Nextcloud, ownCloud or any RFC 4918 server). IDs are
+// paths relative to the configured URL with '/' separators; the root is "".
+pub struct WebdavBackend {
+    client: HttpClient,
+    settings: WebdavConfig,
+    base_url: String,
+    // Decoded path of base_url, stripped from the hrefs in PROPFIND responses
+    base_path: String,
+    chunked_upload_url: Option<String>,
+    authorization: String,
+}
+
+struct DavResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl WebdavBackend {
+    pub async fn connect(settings: &WebdavConfig, network: &NetworkConfig) -> Result<Self, String> {
+        let base_url = settings.url.trim_end_matches('/').to_string();
+        let uri = base_url
+            .parse::<hyper::Uri>()
+            .map_err(|e| format!("Invalid WebDAV URL '{}': {}", settings.url, e))?;
+        if uri.host().is_none() {
+            return Err(format!("Invalid WebDAV URL '{}': no host", settings.url));
+        }
+
+        let credentials = format!("{}:{}", settings.username, settings.password);
+        let chunked_upload_url = settings
+            .chunked_upload_url
+            .clone()
+            .or_else(|| nextcloud_uploads_url(&base_url))
+            .map(|url| url.trim_end_matches('/').to_string());
+        match &chunked_upload_url {
+            Some(url) => log_info(&format!("WebDAV chunked uploads via {}", url)),
+            None => log_info("WebDAV chunked uploads unavailable; large files are sent in one request"),
+        }
+
+        let backend = WebdavBackend {
+            client: https_client(network)?,
+            settings: settings.clone(),
+            base_path: percent_decode(uri.path()).trim_end_matches('/').to_string(),
+            base_url,
+            chunked_upload_url,
+            authorization: format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(credentials)),
+        };
+        // Uploads into a collection that does not exist fail with 409
+        backend.create_root().await?;
+        Ok(backend)
+    }
+
+    // MKCOL on the configured collection; on 409 its parent is missing, so walk
+    // up until a collection can be created (or exists), then back down
+    async fn create_root(&self) -> Result<(), String> {
+        let mut pending = vec![self.url_of("")];
+        while let Some(url) = pending.last().cloned() {
+            if self.make_collection(&url, &url).await? {
+                pending.pop();
+            } else {
+                let parent = parent_collection(&url)
+                    .ok_or_else(|| format!("Failed to create WebDAV folder {}: no parent folder exists", url))?;
+                pending.push(parent);
+            }
+        }
+        Ok(())
+    }
+
+    // False when the parent collection is missing (409); 405 means the
+    // collection already exists
+    async fn make_collection(&self, url: &str, name: &str) -> Result<bool, String> {
+        let response = self.send("MKCOL", url, &[], Bytes::new()).await?;
+        match response.status {
+            StatusCode::CONFLICT => Ok(false),
+            StatusCode::METHOD_NOT_ALLOWED => Ok(true),
+            _ => check(&response, &format!("create folder '{}'", name)).map(|()| true),
+        }
+    }
+
+    fn url_of(&self, id: &str) -> String {
+        match id.trim_matches('/') {
+            "" => format!("{}/", self.base_url),
+            id => format!("{}/{}", self.base_url, uri_encode(id, false)),
+        }
+    }
+
+    async fn send(&self, method: &str, url: &str, headers: &[(&str, String)], body: Bytes) -> Result<DavResponse, String> {
+        self.send_body(method, url, headers, full_body(body)).await
+    }
+
+    async fn send_body(&self, method: &str, url: &str, headers: &[(&str, String)], body: HttpBody) -> Result<DavResponse, String> {
+        let method = Method::from_bytes(method.as_bytes()).map_err(|e| e.to_string())?;
+        let mut request = hyper::Request::builder()
+            .method(method.clone())
+            .uri(url)
+            .header(hyper::header::AUTHORIZATION, self.authorization.as_str())
+            .header(hyper::header::USER_AGENT, concat!("gdrive-stealth-sync/", env!("CARGO_PKG_VERSION")));
+        for (name, value) in headers {
+            request = request.header(*name, value.as_str());
+        }
+        let request = request.body(body).map_err(|e| e.to_string())?;
+
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(|e| format!("WebDAV {} {} failed: {}", method, url, e))?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| format!("Failed to read WebDAV response from {}: {}", url, e))?
+            .to_bytes();
+        Ok(DavResponse { status, headers, body })
+    }
+
+    async fn propfind(&self, id: &str, depth: &str, query: &str) -> Result<Option<String>, String> {
+        let headers = [("Depth", depth.to_string()), ("Content-Type", "application/xml".to_string())];
+        let response = self
+            .send("PROPFIND", &self.url_of(id), &headers, Bytes::from(query.to_string()))
+            .await?;
+        if response.status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        check(&response, &format!("look up '{}'", id))?;
+        Ok(Some(String::from_utf8_lossy(&response.body).to_string()))
+    }
+
+    // Files in a PROPFIND multistatus body, skipping collections
+    fn parse_files(&self, body: &str) -> Vec<RemoteFile> {
+        let mut files = Vec::new();
+        for response in xml_elements(body, "response") {
+            let is_collection = xml_elements(&response, "resourcetype")
+                .first()
+                .is_some_and(|t| t.contains("collection"));
+            let href = match xml_elements(&response, "href").into_iter().next() {
+                Some(href) if !is_collection => href,
+                _ => continue,
+            };
+            let id = self.id_from_href(&href);
+            files.push(RemoteFile {
+                name: id.rsplit('/').next().unwrap_or(&id).to_string(),
+                size: xml_elements(&response, "getcontentlength").first().and_then(|s| s.trim().parse().ok()),
+                // Plain WebDAV has no checksum property; uploads are verified by size
+                md5: None,
+                id,
+            });
+        }
+        files
+    }
+
+    fn id_from_href(&self, href: &str) -> String {
+        // Servers send either a path or a full URL
+        let path = match href.parse::<hyper::Uri>() {
+            Ok(uri) if uri.scheme().is_some() => uri.path().to_string(),
+            _ => href.to_string(),
+        };
+        let path = percent_decode(&path);
+        path.strip_prefix(&self.base_path).unwrap_or(&path).trim_matches('/').to_string()
+    }
+
+    async fn put(&self, id: &str, source: &Path, metadata: &UploadMetadata, create: bool) -> Result<RemoteFile, String> {
+        let size = fs::metadata(source)
+            .map_err(|e| format!("Failed to read metadata of {:?}: {}", source, e))?
+            .len();
+        let chunk_size = (self.settings.chunk_size_mb * 1024 * 1024).max(MIN_CHUNK_SIZE);
+
+        let mut headers = vec![("Content-Type", "application/octet-stream".to_string())];
+        // Nextcloud and ownCloud keep the local modification time when given
+        if let Some(modified) = metadata.modified_time {
+            headers.push(("X-OC-MTime", modified.timestamp().to_string()));
+        }
+
+        match &self.chunked_upload_url {
+            Some(uploads_url) if size > chunk_size => {
+                let chunk_size = chunk_size.max(size.div_ceil(MAX_CHUNKS));
+                self.put_chunked(uploads_url, id, source, size, chunk_size, headers, create).await?;
+            }
+            _ => {
+                if create {
+                    // Never replace a file that appeared since the name conflict check
+                    headers.push(("If-None-Match", "*".to_string()));
+                }
+                // Sized up front so the body is not sent with chunked transfer encoding
+                headers.push(("Content-Length", size.to_string()));
+                let body = file_body(source).await?;
+                let response = self.send_body("PUT", &self.url_of(id), &headers, body).await?;
+                check(&response, &format!("upload '{}'", id))?;
+            }
+        }
+
+        // Report what the server now holds, so the upload can be verified
+        self.metadata(id).await
+    }
+
+    // Nextcloud chunked upload (v2): chunks go into a temporary upload collection,
+    // then a MOVE of its ".file" assembles them at the destination
+    #[allow(clippy::too_many_arguments)]
+    async fn put_chunked(
+        &self,
+        uploads_url: &str,
+        id: &str,
+        source: &Path,
+        size: u64,
+        chunk_size: u64,
+        mut headers: Vec<(&str, String)>,
+        create: bool,
+    ) -> Result<(), String> {
+        let target = self.url_of(id);
+        let transfer_url = format!(
+            "{}/gdrive-stealth-sync-{}",
+            uploads_url,
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        );
+        let destination = [("Destination", target.clone())];
+
+        let response = self.send("MKCOL", &transfer_url, &destination, Bytes::new()).await?;
+        check(&response, &format!("start chunked upload of '{}'", id))?;
+
+        match self.upload_chunks(&transfer_url, &target, source, size, chunk_size).await {
+            Ok(()) => {
+                headers.push(("Destination", target));
+                headers.push(("OC-Total-Length", size.to_string()));
+                if create {
+                    headers.push(("Overwrite", "F".to_string()));
+                }
+                let response = self
+                    .send("MOVE", &format!("{}/.file", transfer_url), &headers, Bytes::new())
+                    .await?;
+                check(&response, &format!("assemble chunked upload of '{}'", id))
+            }
+            Err(e) => {
+                // Remove the partial upload instead of leaving it to the server's cleanup job
+                if let Err(cleanup_error) = self.send("DELETE", &transfer_url, &[], Bytes::new()).await {
+                    log::error!("Failed to remove chunked upload {}: {}", transfer_url, cleanup_error);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn upload_chunks(&self, transfer_url: &str, target: &str, source: &Path, size: u64, chunk_size: u64) -> Result<(), String> {
+        let mut file = fs::File::open(source).map_err(|e| format!("Failed to open file {:?}: {}", source, e))?;
+        let headers = [("Destination", target.to_string()), ("OC-Total-Length", size.to_string())];
+        let mut number = 1u64;
+        loop {
+            let mut buffer = Vec::with_capacity(chunk_size as usize);
+            (&mut file)
+                .take(chunk_size)
+                .read_to_end(&mut buffer)
+                .map_err(|e| format!("Failed to read file {:?}: {}", source, e))?;
+            if buffer.is_empty() {
+                return Ok(());
+            }
+
+            let response = self
+                .send("PUT", &format!("{}/{:05}", transfer_url, number), &headers, Bytes::from(buffer))
+                .await?;
+            check(&response, &format!("upload chunk {} of {:?}", number, source))?;
+            log::debug!("Uploaded chunk {} of {:?}", number, source);
+            number += 1;
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for WebdavBackend {
+    fn describe(&self) -> String {
+        format!("WebDAV folder {}", self.base_url)
+    }
+
+    fn root_folder(&self) -> &str {
+        ""
+    }
+
+    async fn create(&self, folder_id: &str, source: &Path, metadata: &UploadMetadata) -> Result<RemoteFile, String> {
+        let id = child_id(folder_id, &metadata.name);
+        self.put(&id, source, metadata, true).await
+    }
+
+    // Nextcloud and ownCloud keep the replaced content as a file version
+    async fn update(&self, file_id: &str, source: &Path, metadata: &UploadMetadata) -> Result<RemoteFile, String> {
+        self.put(file_id, source, metadata, false).await
+    }
+
+    async fn list(&self, folder_id: &str, name: Option<&str>) -> Result<Vec<RemoteFile>, String> {
+        let (id, depth) = match name {
+            Some(name) => (child_id(folder_id, name), "0"),
+            None => (folder_id.to_string(), "1"),
+        };
+        match self.propfind(&id, depth, PROPFIND_FILE).await? {
+            Some(body) => Ok(self.parse_files(&body)),
+            None if name.is_some() => Ok(Vec::new()),
+            None => Err(format!("WebDAV folder '{}' does not exist", folder_id)),
+        }
+    }
+
+    async fn mkdir(&self, parent_id: &str, name: &str) -> Result<String, String> {
+        let id = child_id(parent_id, name);
+        if !self.make_collection(&format!("{}/", self.url_of(&id)), &id).await? {
+            return Err(format!("Failed to create folder '{}': '{}' does not exist", id, parent_id));
+        }
+        Ok(id)
+    }
+
+    // Nextcloud and ownCloud move deleted files to their trash bin
+    async fn trash(&self, file_id: &str) -> Result<(), String> {
+        let response = self.send("DELETE", &self.url_of(file_id), &[], Bytes::new()).await?;
+        check(&response, &format!("delete '{}'", file_id))
+    }
+
+    async fn metadata(&self, file_id: &str) -> Result<RemoteFile, String> {
+        let body = self
+            .propfind(file_id, "0", PROPFIND_FILE)
+            .await?
+            .ok_or_else(|| format!("Failed to get metadata of '{}': not found", file_id))?;
+        self.parse_files(&body)
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("Failed to get metadata of '{}': not a file", file_id))
+    }
+
+    async fn download(&self, file_id: &str, destination: &Path) -> Result<(), String> {
+        let response = self.send("GET", &self.url_of(file_id), &[], Bytes::new()).await?;
+        check(&response, &format!("download '{}'", file_id))?;
+        fs::write(destination, &response.body).map_err(|e| format!("Failed to write {:?}: {}", destination, e))
+    }
+
+    // RFC 4331 quota properties; a negative available size means no limit
+    async fn quota(&self) -> Result<Option<StorageQuota>, String> {
+        let body = match self.propfind("", "0", PROPFIND_QUOTA).await? {
+            Some(body) => body,
+            None => return Ok(None),
+        };
+        let value = |name: &str| -> Option<i64> { xml_elements(&body, name).first().and_then(|v| v.trim().parse().ok()) };
+        Ok(match (value("quota-used-bytes"), value("quota-available-bytes")) {
+            (Some(usage), Some(available)) if available >= 0 => Some(StorageQuota { usage, limit: usage + available }),
+            _ => None,
+        })
+    }
+}
+
+fn child_id(folder_id: &str, name: &str) -> String {
+    let name = name.replace('\\', "/");
+    match folder_id.trim_matches('/') {
+        "" => name,
+        folder => format!("{}/{}", folder, name),
+    }
+}
+
+fn check(response: &DavResponse, action: &str) -> Result<(), String> {
+    if response.status.is_success() {
+        return Ok(());
+    }
+    let hint = match response.status {
+        StatusCode::UNAUTHORIZED => " (check the username and password)".to_string(),
+        StatusCode::PRECONDITION_FAILED => " (the file already exists)".to_string(),
+        _ => match response.headers.get("x-sabre-message").or_else(|| response.headers.get("x-error-message")) {
+            Some(message) => format!(": {}", message.to_str().unwrap_or_default()),
+            None => String::new(),
+        },
+    };
+    Err(format!("Failed to {}: HTTP {}{}", action, response.status, hint))
+}
+
+// "https://host/a/b/" -> "https://host/a/"; None once only the server root is left
+fn parent_collection(url: &str) -> Option<String> {
+    let url = url.trim_end_matches('/');
+    let authority_start = url.find("://")? + 3;
+    let path_start = authority_start + url[authority_start..].find('/')?;
+    let last = url.rfind('/')?;
+    (last > path_start).then(|| format!("{}/", &url[..last]))
+}
+
+// .../remote.php/dav/files/<user>/... → .../remote.php/dav/uploads/<user>
+fn nextcloud_uploads_url(url: &str) -> Option<String> {
+    let marker = "/remote.php/dav/files/";
+    let start = url.find(marker)?;
+    let user = url[start + marker.len()..].split('/').next().filter(|u| !u.is_empty())?;
+    Some(format!("{}/remote.php/dav/uploads/{}", &url[..start], user))
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+// Contents of every element with this local name, whatever namespace prefix the
+// server uses (<d:href>, <D:href>, <href>)
+fn xml_elements(xml: &str, name: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &rest[..end];
+        let tag_name = tag.split_whitespace().next().unwrap_or_default();
+        let local = tag_name.rsplit(':').next().unwrap_or(tag_name);
+        if tag.starts_with('/') || tag.ends_with('/') || local != name {
+            continue;
+        }
+
+        rest = &rest[end + 1..];
+        let close = format!("</{}>", tag_name);
+        match rest.find(&close) {
+            Some(close_start) => {
+                values.push(xml_unescape(&rest[..close_start]));
+                rest = &rest[close_start + close.len()..];
+            }
+            None => break,
+        }
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    // A minimal WebDAV server holding the given collections: MKCOL answers 201,
+    // 405 when the collection exists or 409 when its parent is missing; PUT
+    // answers 201 and PROPFIND reports the last PUT. Each request's headers and
+    // body are sent back to the test.
+    async fn stand_in_server(
+        listener: TcpListener,
+        collections: &[&str],
+        received: tokio::sync::mpsc::UnboundedSender<(Vec<String>, Vec<u8>)>,
+    ) {
+        let mut collections: HashSet<String> = collections.iter().map(|c| c.to_string()).collect();
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(stream);
+        let mut stored = (String::new(), 0);
+        loop {
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                if stream.read_line(&mut line).await.unwrap() == 0 {
+                    return;
+                }
+                if line == "\r\n" {
+                    break;
+                }
+                headers.push(line.trim_end().to_lowercase());
+            }
+            let length = headers
+                .iter()
+                .find_map(|h| h.strip_prefix("content-length: "))
+                .map_or(0, |v| v.parse().unwrap());
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body).await.unwrap();
+
+            let mut request_line = headers[0].split(' ');
+            let (method, path) = (request_line.next().unwrap().to_string(), request_line.next().unwrap().to_string());
+            let response = match method.as_str() {
+                "mkcol" => {
+                    let parent = parent_collection(&format!("http://host{}", path)).map(|p| p["http://host".len()..].to_string());
+                    if collections.contains(&path) {
+                        "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\n\r\n".to_string()
+                    } else if parent.is_some_and(|p| !collections.contains(&p)) {
+                        "HTTP/1.1 409 Conflict\r\nContent-Length: 0\r\n\r\n".to_string()
+                    } else {
+                        collections.insert(path);
+                        "HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n".to_string()
+                    }
+                }
+                "put" => {
+                    stored = (path, body.len());
+                    "HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n".to_string()
+                }
+                _ => {
+                    let xml = format!(
+                        "<d:multistatus xmlns:d=\"DAV:\"><d:response><d:href>{}</d:href><d:propstat><d:prop>\
+                         <d:resourcetype/><d:getcontentlength>{}</d:getcontentlength></d:prop></d:propstat></d:response></d:multistatus>",
+                        stored.0, stored.1
+                    );
+                    format!("HTTP/1.1 207 Multi-Status\r\nContent-Length: {}\r\n\r\n{}", xml.len(), xml)
+                }
+            };
+            received.send((headers, body)).unwrap();
+            stream.get_mut().write_all(response.as_bytes()).await.unwrap();
+        }
+    }
+
+    async fn connect(path: &str, collections: &'static [&'static str]) -> (WebdavBackend, tokio::sync::mpsc::UnboundedReceiver<(Vec<String>, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}{}", listener.local_addr().unwrap(), path);
+        let (sender, received) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(stand_in_server(listener, collections, sender));
+
+        let settings: WebdavConfig = serde_json::from_value(serde_json::json!({
+            "url": url,
+            "username": "alice",
+            "password": "secret",
+        }))
+        .unwrap();
+        (WebdavBackend::connect(&settings, &NetworkConfig::default()).await.unwrap(), received)
+    }
+
+    fn request_lines(received: &mut tokio::sync::mpsc::UnboundedReceiver<(Vec<String>, Vec<u8>)>) -> Vec<String> {
+        let mut lines = Vec::new();
+        while let Ok((headers, _)) = received.try_recv() {
+            lines.push(headers[0].clone());
+        }
+        lines
+    }
+
+    #[tokio::test]
+    async fn streams_uploads_with_a_content_length() {
+        let (backend, mut received) = connect("/dav", &["/dav/"]).await;
+        assert_eq!(request_lines(&mut received), vec!["mkcol /dav/ http/1.1"]);
+
+        // Larger than one read of the file, so the body arrives in several frames
+        let content: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let dir = crate::test_support::temp_dir("webdav-stream");
+        let source = dir.join("big.bin");
+        fs::write(&source, &content).unwrap();
+
+        let metadata = UploadMetadata {
+            name: "big.bin".to_string(),
+            ..Default::default()
+        };
+        let file = backend.create("", &source, &metadata).await.unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(file.size, Some(content.len() as u64));
+        let (headers, body) = received.recv().await.unwrap();
+        assert_eq!(headers[0], "put /dav/big.bin http/1.1");
+        assert!(headers.contains(&format!("content-length: {}", content.len())));
+        assert!(headers.contains(&"if-none-match: *".to_string()));
+        assert!(!headers.iter().any(|h| h.starts_with("transfer-encoding")));
+        assert!(body == content);
+    }
+
+    #[tokio::test]
+    async fn creates_the_configured_collection_and_missing_parents() {
+        let (backend, mut received) = connect("/dav/backups/scans", &["/dav/"]).await;
+        assert_eq!(
+            request_lines(&mut received),
+            vec![
+                "mkcol /dav/backups/scans/ http/1.1",
+                "mkcol /dav/backups/ http/1.1",
+                "mkcol /dav/backups/scans/ http/1.1",
+            ]
+        );
+
+        assert_eq!(backend.mkdir("", "2024").await.unwrap(), "2024");
+        assert_eq!(backend.mkdir("2024", "q1").await.unwrap(), "2024/q1");
+        assert!(backend.mkdir("missing", "q1").await.is_err());
+    }
+
+    #[test]
+    fn walks_up_to_the_server_root() {
+        assert_eq!(parent_collection("https://host/a/b/").as_deref(), Some("https://host/a/"));
+        assert_eq!(parent_collection("https://host:8080/a/b").as_deref(), Some("https://host:8080/a/"));
+        assert_eq!(parent_collection("https://host/a/"), None);
+        assert_eq!(parent_collection("https://host/"), None);
+    }
+}
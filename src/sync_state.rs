@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::local_files;
+
+// What was last uploaded for each local file, keyed by path relative to the
+// watched folder ('/' separators). Persisted as sync_state.json next to the exe.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SyncRecord {
+    pub size: u64,
+    // Local modification time, seconds since the Unix epoch
+    pub modified: i64,
+    // MD5 of the bytes that were uploaded (the ciphertext when encrypting)
+    pub md5: String,
+    pub drive_file_id: String,
+    pub remote_name: String,
+    // Drive reported the same size and md5Checksum as the local upload
+    pub verified: bool,
+    pub uploaded_at: String,
+}
+
+pub struct SyncState {
+    path: PathBuf,
+    records: BTreeMap<String, SyncRecord>,
+}
+
+impl SyncState {
+    pub fn load(path: PathBuf) -> Result<Self, String> {
+        let records = if path.exists() {
+            let content = fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read sync state {:?}: {}", path, e))?;
+            serde_json::from_str(&content)
+                .map_err(|e| format!("Failed to parse sync state {:?}: {}", path, e))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(SyncState { path, records })
+    }
+
+    // Records an upload and writes the state file straight away
+    pub fn insert(&mut self, relative_path: &str, record: SyncRecord) -> Result<(), String> {
+        self.records.insert(normalize(relative_path), record);
+        self.save()
+    }
+
+    // No file has been recorded yet: a new install, or one upgraded from a
+    // version that kept no sync state
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    // Relative paths and records of files with an uploaded copy
+    pub fn uploaded(&self) -> impl Iterator<Item = (&str, &SyncRecord)> {
+        self.records
+            .iter()
+            .filter(|(_, record)| !record.drive_file_id.is_empty())
+            .map(|(path, record)| (path.as_str(), record))
+    }
+
+    // The file has an uploaded copy and has not changed since. A record without a
+    // Drive file ID is a failed upload that still needs to be retried.
+    pub fn is_up_to_date(&self, relative_path: &str, file_path: &Path) -> bool {
+        let record = match self.records.get(&normalize(relative_path)) {
+            Some(record) => record,
+            None => return false,
+        };
+        let size = match fs::metadata(file_path) {
+            Ok(m) => m.len(),
+            Err(_) => return false,
+        };
+        !record.drive_file_id.is_empty() && record.size == size && record.modified == modified_secs(file_path)
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(&self.records)
+            .map_err(|e| format!("Failed to serialize sync state: {}", e))?;
+        local_files::write_atomically(&self.path, |file| {
+            file.write_all(json.as_bytes()).map_err(|e| e.to_string())
+        })
+        .map_err(|e| format!("Failed to save sync state {:?}: {}", self.path, e))
+    }
+}
+
+pub fn normalize(relative_path: &str) -> String {
+    relative_path.replace('\\', "/")
+}
+
+// Local modification time in seconds since the Unix epoch, 0 if unavailable
+pub fn modified_secs(path: &Path) -> i64 {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
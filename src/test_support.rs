@@ -0,0 +1,13 @@
+// Helpers shared by the unit tests
+
+use std::fs;
+use std::path::PathBuf;
+
+// A fresh, empty directory under the system temp dir. Tests run in parallel,
+// so every test needs a name of its own.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("gdrive-sync-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,296 @@
+use hmac::{Hmac, Mac};
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper_util::client::legacy::Client;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+use crate::config::{NetworkConfig, WebhookConfig, WebhookEvent};
+use crate::network;
+
+type WebhookClient = Client<network::Connector, Full<Bytes>>;
+
+// Per request; a slow endpoint costs a retry, never an upload
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+const RETRY_MAX_SECS: u64 = 300;
+// Deliveries waiting or retrying; events beyond this are dropped rather than
+// piling up tasks during a large sync
+const MAX_QUEUED_DELIVERIES: usize = 500;
+const MAX_CONCURRENT_DELIVERIES: usize = 4;
+
+// JSON body posted to each webhook
+#[derive(Serialize, Clone)]
+pub struct Notification {
+    pub event: WebhookEvent,
+    pub timestamp: String,
+    pub host: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<FileDetails>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota: Option<QuotaDetails>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch: Option<BatchDetails>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct FileDetails {
+    pub local_path: String,
+    pub remote_name: String,
+    pub size: u64,
+    pub drive_file_id: String,
+    pub destination_folder: String,
+    pub attempts: u32,
+    pub error: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct QuotaDetails {
+    pub usage_bytes: i64,
+    pub limit_bytes: i64,
+    pub percent_used: f64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct BatchDetails {
+    pub scan: String,
+    pub uploaded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub up_to_date: usize,
+}
+
+impl Notification {
+    pub fn new(event: WebhookEvent, message: &str) -> Self {
+        Notification {
+            event,
+            timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            host: crate::gdrive_sync::source_hostname(),
+            message: message.to_string(),
+            file: None,
+            quota: None,
+            batch: None,
+        }
+    }
+}
+
+struct Webhooks {
+    hooks: Vec<WebhookConfig>,
+    #[cfg(windows)]
+    network: NetworkConfig,
+    client: WebhookClient,
+    // Deliveries still running, so one-shot runs can wait for them before exiting
+    pending: Mutex<Vec<tokio::task::JoinHandle<()>>>,
+    queue: Arc<Semaphore>,
+    deliveries: Arc<Semaphore>,
+    // Set while events are being dropped, so the overflow is logged once
+    overflowing: AtomicBool,
+}
+
+// Global webhook list, set up once by the sync loop when webhooks are configured
+static WEBHOOKS: once_cell::sync::OnceCell<Webhooks> = once_cell::sync::OnceCell::new();
+
+pub fn init_webhooks(hooks: &[WebhookConfig], network: &NetworkConfig) -> Result<(), String> {
+    for hook in hooks {
+        hook.url
+            .parse::<hyper::Uri>()
+            .map_err(|e| format!("Invalid webhook URL '{}': {}", hook.url, e))?;
+    }
+    let webhooks = Webhooks {
+        hooks: hooks.to_vec(),
+        #[cfg(windows)]
+        network: network.clone(),
+        client: build_client(network)?,
+        pending: Mutex::new(Vec::new()),
+        queue: Arc::new(Semaphore::new(MAX_QUEUED_DELIVERIES)),
+        deliveries: Arc::new(Semaphore::new(MAX_CONCURRENT_DELIVERIES)),
+        overflowing: AtomicBool::new(false),
+    };
+    let _ = WEBHOOKS.set(webhooks);
+    Ok(())
+}
+
+// Queues the notification for every webhook subscribed to its event and returns
+// straight away; each delivery retries on its own task, with a few posting at a
+// time. Must be called from within the tokio runtime.
+pub fn send(notification: Notification) {
+    let webhooks = match WEBHOOKS.get() {
+        Some(webhooks) => webhooks,
+        None => return,
+    };
+
+    let mut pending = webhooks.pending.lock().unwrap_or_else(|e| e.into_inner());
+    pending.retain(|handle| !handle.is_finished());
+    for hook in webhooks.hooks.iter().filter(|h| subscribed(h, notification.event)) {
+        let queued = match webhooks.queue.clone().try_acquire_owned() {
+            Ok(permit) => {
+                webhooks.overflowing.store(false, Ordering::Relaxed);
+                permit
+            }
+            Err(_) => {
+                if !webhooks.overflowing.swap(true, Ordering::Relaxed) {
+                    log::error!(
+                        "{} webhook deliveries are already queued; dropping new events until the queue drains",
+                        MAX_QUEUED_DELIVERIES
+                    );
+                }
+                log::debug!("Dropped '{}' webhook to {}", notification.event.name(), hook.url);
+                continue;
+            }
+        };
+        let hook = hook.clone();
+        let client = webhooks.client.clone();
+        let deliveries = webhooks.deliveries.clone();
+        let notification = notification.clone();
+        pending.push(tokio::spawn(async move {
+            let _queued = queued;
+            let _delivering = deliveries.acquire_owned().await;
+            deliver(&client, &hook, &notification).await;
+        }));
+    }
+}
+
+// Waits up to `timeout` for queued deliveries, for runs that exit afterwards
+pub async fn flush(timeout: Duration) {
+    let handles = match WEBHOOKS.get() {
+        Some(webhooks) => std::mem::take(&mut *webhooks.pending.lock().unwrap_or_else(|e| e.into_inner())),
+        None => return,
+    };
+    let all = async {
+        for handle in handles {
+            let _ = handle.await;
+        }
+    };
+    if tokio::time::timeout(timeout, all).await.is_err() {
+        log::error!("Gave up waiting for webhook deliveries after {}s", timeout.as_secs());
+    }
+}
+
+// For the service stop event, which is raised outside the sync loop's runtime:
+// delivers on a runtime of its own and waits at most `timeout`
+#[cfg(windows)]
+pub fn send_blocking(notification: Notification, timeout: Duration) {
+    let webhooks = match WEBHOOKS.get() {
+        Some(webhooks) => webhooks,
+        None => return,
+    };
+    let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(rt) => rt,
+        Err(e) => {
+            log::error!("Failed to create runtime for webhook delivery: {}", e);
+            return;
+        }
+    };
+
+    runtime.block_on(async {
+        // The shared client's pooled connections belong to the sync loop's runtime
+        let client = match build_client(&webhooks.network) {
+            Ok(client) => client,
+            Err(e) => {
+                log::error!("{}", e);
+                return;
+            }
+        };
+        let hooks = webhooks.hooks.iter().filter(|h| subscribed(h, notification.event));
+        let all = async {
+            for hook in hooks {
+                deliver(&client, hook, &notification).await;
+            }
+        };
+        if tokio::time::timeout(timeout, all).await.is_err() {
+            log::error!("Gave up delivering '{}' webhooks after {}s", notification.event.name(), timeout.as_secs());
+        }
+    });
+}
+
+// An empty event list subscribes to everything but per-file successes, which
+// the sync_batch summary covers without a request per file
+fn subscribed(hook: &WebhookConfig, event: WebhookEvent) -> bool {
+    if hook.events.is_empty() {
+        event != WebhookEvent::UploadSucceeded
+    } else {
+        hook.events.contains(&event)
+    }
+}
+
+fn build_client(network: &NetworkConfig) -> Result<WebhookClient, String> {
+    let connector = network::connector(network).map_err(|e| format!("Failed to set up webhook client: {}", e))?;
+    Ok(Client::builder(hyper_util::rt::TokioExecutor::new()).build(connector))
+}
+
+async fn deliver(client: &WebhookClient, hook: &WebhookConfig, notification: &Notification) {
+    let body = match serde_json::to_vec(notification) {
+        Ok(body) => Bytes::from(body),
+        Err(e) => {
+            log::error!("Failed to serialize webhook payload: {}", e);
+            return;
+        }
+    };
+
+    let max_attempts = hook.max_attempts.max(1);
+    for attempt in 1..=max_attempts {
+        if attempt > 1 {
+            let delay = Duration::from_secs((hook.retry_delay_secs.max(1) << (attempt - 2).min(16)).min(RETRY_MAX_SECS));
+            tokio::time::sleep(delay).await;
+        }
+        match post(client, hook, body.clone()).await {
+            Ok(()) => {
+                log::debug!("Delivered '{}' webhook to {}", notification.event.name(), hook.url);
+                return;
+            }
+            Err(e) => log::error!(
+                "Failed to deliver '{}' webhook to {} (attempt {}/{}): {}",
+                notification.event.name(), hook.url, attempt, max_attempts, e
+            ),
+        }
+    }
+}
+
+async fn post(client: &WebhookClient, hook: &WebhookConfig, body: Bytes) -> Result<(), String> {
+    let mut request = hyper::Request::builder()
+        .method(hyper::Method::POST)
+        .uri(&hook.url)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .header(hyper::header::USER_AGENT, concat!("gdrive-stealth-sync/", env!("CARGO_PKG_VERSION")));
+    // Lets the receiver check that the payload came from us and was not altered
+    if let Some(secret) = &hook.secret {
+        let mut mac = <Hmac<sha2::Sha256> as Mac>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+        mac.update(&body);
+        request = request.header("X-Signature-256", format!("sha256={:x}", mac.finalize().into_bytes()));
+    }
+    let request = request.body(Full::new(body)).map_err(|e| e.to_string())?;
+
+    let response = tokio::time::timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS), client.request(request))
+        .await
+        .map_err(|_| format!("no response within {}s", REQUEST_TIMEOUT_SECS))?
+        .map_err(|e| e.to_string())?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("HTTP {}", response.status()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook(events: Vec<WebhookEvent>) -> WebhookConfig {
+        serde_json::from_value(serde_json::json!({ "url": "https://hooks.example.com", "events": events })).unwrap()
+    }
+
+    #[test]
+    fn empty_event_list_leaves_out_per_file_successes() {
+        let all = hook(Vec::new());
+        assert!(!subscribed(&all, WebhookEvent::UploadSucceeded));
+        assert!(subscribed(&all, WebhookEvent::SyncBatch));
+        assert!(subscribed(&all, WebhookEvent::UploadFailed));
+
+        let explicit = hook(vec![WebhookEvent::UploadSucceeded]);
+        assert!(subscribed(&explicit, WebhookEvent::UploadSucceeded));
+        assert!(!subscribed(&explicit, WebhookEvent::SyncBatch));
+    }
+}